@@ -0,0 +1,382 @@
+//! Reading and writing through tokio's `AsyncRead` and `AsyncWrite`, enabled
+//! by the `tokio` feature.
+//!
+//! The parser itself is synchronous, so input is only handed to it once
+//! enough of it has arrived. `from_async_reader` holds the whole document in
+//! memory, bounded by `Limits::max_input_bytes`, while `RecordStream` holds
+//! one child of the root element at a time. `to_async_writer` writes as it
+//! serializes, holding back at most `WRITE_BUFFER_SIZE` bytes while the
+//! writer is not ready.
+
+use std::future::Future;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+
+use futures_core::Stream;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::binary::BinaryEncoding;
+use super::de::split::{RecordSplitter, Split};
+use super::de::Options;
+use super::lexical::LexicalForm;
+use super::limits::{Limit, Limits};
+use super::maps::MapRepresentation;
+use super::error::{self, Result};
+use super::ser::SerializerBuilder;
+
+/// Reads a whole document into memory, then deserializes it. Use
+/// `RecordStream` for documents too large for that.
+pub fn from_async_reader<R, T>(reader: R) -> ReadDocument<R, T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    ReadDocument { reader, buffer: Vec::new(), options: Options::default(), value: PhantomData }
+}
+
+/// Most output `WriteDocument` holds back while its writer is not ready.
+pub const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Serializes a value as `to_writer` would, writing it as it goes.
+pub fn to_async_writer<W, S>(writer: W, value: &S) -> WriteDocument<'_, W, S>
+where
+    W: AsyncWrite + Unpin,
+    S: Serialize,
+{
+    SerializerBuilder::new().to_async_writer(writer, value)
+}
+
+impl SerializerBuilder {
+    pub fn to_async_writer<'a, W, S>(&self, writer: W, value: &'a S) -> WriteDocument<'a, W, S>
+    where
+        W: AsyncWrite + Unpin,
+        S: Serialize,
+    {
+        WriteDocument {
+            writer,
+            value,
+            builder: self.clone(),
+            pending: Vec::new(),
+            position: 0,
+            produced: 0,
+            serialized: false,
+        }
+    }
+}
+
+/// Future returned by `from_async_reader`.
+pub struct ReadDocument<R, T> {
+    reader: R,
+    buffer: Vec<u8>,
+    options: Options,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<R, T> ReadDocument<R, T> {
+    /// Sets the bounds on the document, `Limits::default()` unless changed.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    /// Sets how byte strings are expected to be encoded, base64 by default.
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.options.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Sets which lexical forms are accepted for booleans and numbers, XML
+    /// Schema ones by default.
+    pub fn with_lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.options.format.lexical_form = lexical_form;
+        self
+    }
+
+    /// Sets how maps are expected to be written, one element named after each
+    /// key by default.
+    pub fn with_map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.options.map_representation = map_representation;
+        self
+    }
+}
+
+impl<R: AsyncRead + Unpin, T: DeserializeOwned> Future for ReadDocument<R, T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T>> {
+        let this = self.get_mut();
+        loop {
+            let limit = this.options.limits.max_input_bytes;
+            if this.buffer.len() > limit {
+                return Poll::Ready(Err(error::limit_exceeded(Limit::InputBytes, limit)));
+            }
+            match poll_fill(&mut this.reader, cx, &mut this.buffer) {
+                Poll::Ready(Ok(true)) => {},
+                Poll::Ready(Ok(false)) => return Poll::Ready(this.options.deserialize(&this.buffer, None)),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Future returned by `to_async_writer`.
+///
+/// Output goes straight to the writer while it is ready. Once it falls
+/// behind by more than `WRITE_BUFFER_SIZE` bytes, serialization stops, and
+/// starts over when the writer has caught up, skipping what was produced
+/// already. The value is expected to serialize the same way every time.
+pub struct WriteDocument<'a, W, S: 'a> {
+    writer: W,
+    value: &'a S,
+    builder: SerializerBuilder,
+    /// Output held back, and how much of it was written since.
+    pending: Vec<u8>,
+    position: usize,
+    /// Bytes of output written or held back so far.
+    produced: usize,
+    serialized: bool,
+}
+
+impl<'a, W: AsyncWrite + Unpin, S: Serialize> Future for WriteDocument<'a, W, S> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        loop {
+            while this.position < this.pending.len() {
+                match poll_write(&mut this.writer, cx, &this.pending[this.position..]) {
+                    Poll::Ready(Ok(n)) => this.position += n,
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            this.pending.clear();
+            this.position = 0;
+            if this.serialized {
+                return Pin::new(&mut this.writer).poll_flush(cx).map_err(error::io);
+            }
+
+            let mut sink = Sink {
+                writer: &mut this.writer,
+                cx: &mut *cx,
+                skip: this.produced,
+                pending: &mut this.pending,
+                produced: 0,
+                full: false,
+                error: None,
+            };
+            let result = this.builder.to_writer(&mut sink, this.value);
+            let (produced, full, err) = (sink.produced, sink.full, sink.error.take());
+            this.produced = produced;
+            match (result, err) {
+                (_, Some(err)) => return Poll::Ready(Err(err)),
+                (Ok(()), None) => this.serialized = true,
+                (Err(_), None) if full => {},
+                (Err(err), None) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+}
+
+/// Output of a `WriteDocument` pass, which skips the output of previous
+/// passes, writes while the writer is ready, then holds back what follows.
+struct Sink<'s, 'c: 's, W: 's> {
+    writer: &'s mut W,
+    cx: &'s mut Context<'c>,
+    skip: usize,
+    pending: &'s mut Vec<u8>,
+    produced: usize,
+    /// Whether the pass stopped for lack of room.
+    full: bool,
+    /// Error from the writer, rather than from serialization.
+    error: Option<error::Error>,
+}
+
+impl<'s, 'c, W: AsyncWrite + Unpin> Write for Sink<'s, 'c, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let skipped = self.skip.saturating_sub(self.produced).min(buf.len());
+        let mut rest = &buf[skipped..];
+        while self.pending.is_empty() && !rest.is_empty() {
+            match poll_write(self.writer, self.cx, rest) {
+                Poll::Ready(Ok(n)) => rest = &rest[n..],
+                Poll::Ready(Err(err)) => {
+                    self.error = Some(err);
+                    return Err(io::Error::other("write failed"));
+                },
+                Poll::Pending => break,
+            }
+        }
+        let room = WRITE_BUFFER_SIZE - self.pending.len();
+        if rest.len() > room && buf.len() - rest.len() + room == 0 {
+            self.full = true;
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        let held = rest.len().min(room);
+        self.pending.extend_from_slice(&rest[..held]);
+        let accepted = buf.len() - rest.len() + held;
+        self.produced += accepted;
+        Ok(accepted)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes part of `buf`, returning how much.
+fn poll_write<W: AsyncWrite + Unpin>(writer: &mut W, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+    match Pin::new(writer).poll_write(cx, buf) {
+        Poll::Ready(Ok(0)) => Poll::Ready(Err(error::io(io::Error::from(io::ErrorKind::WriteZero)))),
+        Poll::Ready(Ok(n)) => Poll::Ready(Ok(n)),
+        Poll::Ready(Err(err)) => Poll::Ready(Err(error::io(err))),
+        Poll::Pending => Poll::Pending,
+    }
+}
+
+/// Deserializes the children of the root element of a document one at a
+/// time, as they are read, for documents too large to hold in memory.
+///
+/// Namespaces declared on the root element apply to the records. As a
+/// `Stream`, it ends once the root element is closed, or after malformed
+/// markup or an I/O error.
+pub struct RecordStream<R, T> {
+    reader: R,
+    splitter: RecordSplitter,
+    done: bool,
+    options: Options,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<R: AsyncRead + Unpin, T: DeserializeOwned> RecordStream<R, T> {
+    pub fn new(reader: R) -> Self {
+        RecordStream {
+            reader,
+            splitter: RecordSplitter::new(),
+            done: false,
+            options: Options::default(),
+            value: PhantomData,
+        }
+    }
+
+    /// Sets the bounds on each record, `Limits::default()` unless changed.
+    /// `max_input_bytes` also bounds what is buffered until one is complete.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self.splitter.set_limit(limits.max_input_bytes);
+        self
+    }
+
+    /// Sets how byte strings are expected to be encoded, base64 by default.
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.options.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Sets which lexical forms are accepted for booleans and numbers, XML
+    /// Schema ones by default.
+    pub fn with_lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.options.format.lexical_form = lexical_form;
+        self
+    }
+
+    /// Sets how maps are expected to be written, one element named after each
+    /// key by default.
+    pub fn with_map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.options.map_representation = map_representation;
+        self
+    }
+
+    /// Resolves to the next record, or `None` once the root element is
+    /// closed.
+    pub fn next_record(&mut self) -> NextRecord<'_, R, T> {
+        NextRecord { stream: self }
+    }
+
+    pub fn poll_next_record(&mut self, cx: &mut Context) -> Poll<Result<Option<T>>> {
+        let mut buffer = Vec::new();
+        loop {
+            if self.done {
+                return Poll::Ready(Ok(None));
+            }
+            match self.splitter.next() {
+                Ok(Split::Record(record)) => {
+                    let encoding = self.splitter.encoding();
+                    return Poll::Ready(self.options.deserialize(&record, Some(encoding)).map(Some));
+                },
+                Ok(Split::End) => {
+                    self.done = true;
+                    return Poll::Ready(Ok(None));
+                },
+                Ok(Split::Root) => continue,
+                Ok(Split::NeedMore) => {},
+                Err(err) => {
+                    self.done = true;
+                    return Poll::Ready(Err(err));
+                },
+            }
+
+            buffer.clear();
+            match poll_fill(&mut self.reader, cx, &mut buffer) {
+                Poll::Ready(Ok(true)) => self.splitter.extend(&buffer),
+                Poll::Ready(Ok(false)) => {
+                    self.done = true;
+                    return Poll::Ready(Err(error::with_message(if self.splitter.is_incomplete() {
+                        "unexpected end of document in the middle of a record".into()
+                    } else {
+                        "unexpected end of document before the root element was closed".into()
+                    })));
+                },
+                Poll::Ready(Err(err)) => {
+                    self.done = true;
+                    return Poll::Ready(Err(err));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, T: DeserializeOwned> Stream for RecordStream<R, T> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Result<T>>> {
+        self.get_mut().poll_next_record(cx).map(Result::transpose)
+    }
+}
+
+/// Future returned by `RecordStream::next_record`.
+pub struct NextRecord<'a, R: 'a, T: 'a> {
+    stream: &'a mut RecordStream<R, T>,
+}
+
+impl<'a, R: AsyncRead + Unpin, T: DeserializeOwned> Future for NextRecord<'a, R, T> {
+    type Output = Result<Option<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Option<T>>> {
+        self.get_mut().stream.poll_next_record(cx)
+    }
+}
+
+/// Appends the next chunk of input to `buffer`, returning whether there was
+/// any.
+fn poll_fill<R: AsyncRead + Unpin>(
+    reader: &mut R, cx: &mut Context, buffer: &mut Vec<u8>
+) -> Poll<Result<bool>> {
+    let mut chunk = [0; 8192];
+    let mut chunk = ReadBuf::new(&mut chunk);
+    match Pin::new(reader).poll_read(cx, &mut chunk) {
+        Poll::Ready(Ok(())) => {
+            buffer.extend_from_slice(chunk.filled());
+            Poll::Ready(Ok(!chunk.filled().is_empty()))
+        },
+        Poll::Ready(Err(err)) => Poll::Ready(Err(error::io(err))),
+        Poll::Pending => Poll::Pending,
+    }
+}
@@ -0,0 +1,48 @@
+use super::error::{self, Result};
+
+/// Lexical representation used for byte strings (`serialize_bytes` and
+/// `deserialize_bytes`), after the XML Schema binary types.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    /// `xs:base64Binary`
+    #[default]
+    Base64,
+    /// `xs:hexBinary`
+    Hex,
+}
+
+impl BinaryEncoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            BinaryEncoding::Base64 => base64::encode(bytes),
+            BinaryEncoding::Hex => bytes.iter().map(|b| format!("{:02X}", b)).collect(),
+        }
+    }
+
+    pub fn decode(self, s: &str) -> Result<Vec<u8>> {
+        match self {
+            BinaryEncoding::Base64 => {
+                // Encoders commonly wrap base64 at 76 columns, so ignore any
+                // whitespace rather than just the surrounding blanks.
+                let compact: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+                base64::decode(&compact).map_err(error::base64)
+            },
+            BinaryEncoding::Hex => decode_hex(s.trim()),
+        }
+    }
+}
+
+// `is_multiple_of` needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return Err(error::with_message(format!("odd number of digits in hex binary {:?}", s)));
+    }
+    s.as_bytes().chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair).ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| error::with_message(format!("invalid hex binary {:?}", s)))
+        })
+        .collect()
+}
@@ -0,0 +1,284 @@
+//! Canonical XML 1.0 and Exclusive XML Canonicalization, for output that
+//! can be hashed and signed.
+//!
+//! ```
+//! # extern crate serde_xml;
+//! use serde_xml::c14n::{canonicalize, Canonicalization};
+//!
+//! # fn main() {
+//! let input = r#"<?xml version="1.0"?><a z="1" a='2'><b/></a>"#;
+//! let output = canonicalize(input, Canonicalization::C14n).unwrap();
+//! assert_eq!(r#"<a a="2" z="1"><b></b></a>"#, output);
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+use xml::namespace::{Namespace, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+use super::de::scan::{scan_markup, Markup};
+use super::de::DecodingReader;
+use super::error::{self, Result};
+
+/// Canonicalization algorithm, named after its W3C identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canonicalization {
+    C14n,
+    C14nWithComments,
+    ExclusiveC14n,
+    ExclusiveC14nWithComments,
+}
+
+impl Canonicalization {
+    /// Algorithm URI, as used in XML signatures.
+    pub fn algorithm(self) -> &'static str {
+        match self {
+            Canonicalization::C14n => "http://www.w3.org/TR/2001/REC-xml-c14n-20010315",
+            Canonicalization::C14nWithComments => {
+                "http://www.w3.org/TR/2001/REC-xml-c14n-20010315#WithComments"
+            },
+            Canonicalization::ExclusiveC14n => "http://www.w3.org/2001/10/xml-exc-c14n#",
+            Canonicalization::ExclusiveC14nWithComments => {
+                "http://www.w3.org/2001/10/xml-exc-c14n#WithComments"
+            },
+        }
+    }
+
+    fn exclusive(self) -> bool {
+        match self {
+            Canonicalization::ExclusiveC14n | Canonicalization::ExclusiveC14nWithComments => true,
+            Canonicalization::C14n | Canonicalization::C14nWithComments => false,
+        }
+    }
+
+    fn with_comments(self) -> bool {
+        match self {
+            Canonicalization::C14nWithComments | Canonicalization::ExclusiveC14nWithComments => true,
+            Canonicalization::C14n | Canonicalization::ExclusiveC14n => false,
+        }
+    }
+}
+
+pub fn canonicalize(input: &str, method: Canonicalization) -> Result<String> {
+    let mut writer = Vec::with_capacity(input.len());
+    canonicalize_reader(input.as_bytes(), &mut writer, method)?;
+    String::from_utf8(writer).map_err(error::from_utf8)
+}
+
+/// The input is decoded according to its byte order mark or declaration.
+pub fn canonicalize_reader<R: Read, W: Write>(
+    reader: R, writer: W, method: Canonicalization
+) -> Result<()> {
+    let mut input = String::new();
+    DecodingReader::new(reader).read_to_string(&mut input).map_err(error::io)?;
+    let input = normalize(&input);
+
+    let config = ParserConfig::new()
+        .trim_whitespace(false)
+        .whitespace_to_characters(true)
+        .cdata_to_characters(true)
+        .coalesce_characters(true)
+        .ignore_comments(!method.with_comments());
+
+    let mut canonicalizer = Canonicalizer {
+        writer,
+        method,
+        rendered: Vec::new(),
+        depth: 0,
+        after_root: false,
+    };
+
+    for event in EventReader::new_with_config(input.as_bytes(), config) {
+        canonicalizer.event(event.map_err(error::reader)?)?;
+    }
+    canonicalizer.writer.flush().map_err(error::io)
+}
+
+struct Canonicalizer<W: Write> {
+    writer: W,
+    method: Canonicalization,
+    /// Namespace declarations in scope in the output, per open element.
+    rendered: Vec<BTreeMap<String, String>>,
+    depth: usize,
+    after_root: bool,
+}
+
+impl<W: Write> Canonicalizer<W> {
+    fn event(&mut self, event: XmlEvent) -> Result<()> {
+        match event {
+            XmlEvent::StartElement { name, attributes, namespace } => {
+                self.start_element(&name, attributes, &namespace)
+            },
+            XmlEvent::EndElement { name } => {
+                self.rendered.pop();
+                self.depth -= 1;
+                if self.depth == 0 {
+                    self.after_root = true;
+                }
+                self.write(&format!("</{}>", qualified_name(&name)))
+            },
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text) => {
+                if self.depth > 0 {
+                    self.write(&escape_text(&text))
+                } else {
+                    Ok(())
+                }
+            },
+            XmlEvent::ProcessingInstruction { name, data } => {
+                let pi = match data.as_ref().map(|data| data.trim_start()) {
+                    Some(data) if !data.is_empty() => format!("<?{} {}?>", name, data),
+                    _ => format!("<?{}?>", name),
+                };
+                self.outside_markup(&pi)
+            },
+            XmlEvent::Comment(text) => self.outside_markup(&format!("<!--{}-->", text)),
+            XmlEvent::StartDocument { .. } | XmlEvent::EndDocument => Ok(()),
+        }
+    }
+
+    /// Writes markup, separating it from the root element by a line feed when
+    /// it comes before or after it.
+    fn outside_markup(&mut self, markup: &str) -> Result<()> {
+        if self.depth > 0 {
+            self.write(markup)
+        } else if self.after_root {
+            self.write(&format!("\n{}", markup))
+        } else {
+            self.write(&format!("{}\n", markup))
+        }
+    }
+
+    fn start_element(
+        &mut self, name: &OwnedName, attributes: Vec<OwnedAttribute>, namespace: &Namespace
+    ) -> Result<()> {
+        let parent = self.rendered.last().cloned().unwrap_or_default();
+
+        let in_scope = namespace.0.iter()
+            .filter(|(prefix, _)| prefix.as_str() != NS_XML_PREFIX && prefix.as_str() != NS_XMLNS_PREFIX);
+        let candidates: Vec<(&String, &String)> = if self.method.exclusive() {
+            let mut utilized = vec![name.prefix.clone().unwrap_or_default()];
+            utilized.extend(attributes.iter().filter_map(|a| a.name.prefix.clone()));
+            in_scope.filter(|(prefix, _)| utilized.contains(prefix)).collect()
+        } else {
+            in_scope.collect()
+        };
+
+        let mut declarations = BTreeMap::new();
+        for (prefix, uri) in candidates {
+            let inherited = parent.get(prefix).map(String::as_str).unwrap_or("");
+            if inherited != uri {
+                declarations.insert(prefix.clone(), uri.clone());
+            }
+        }
+
+        let mut scope = parent;
+        scope.extend(declarations.iter().map(|(p, u)| (p.clone(), u.clone())));
+        self.rendered.push(scope);
+        self.depth += 1;
+
+        let mut tag = format!("<{}", qualified_name(name));
+        for (prefix, uri) in &declarations {
+            if prefix.is_empty() {
+                tag.push_str(&format!(" xmlns=\"{}\"", escape_attribute(uri)));
+            } else {
+                tag.push_str(&format!(" xmlns:{}=\"{}\"", prefix, escape_attribute(uri)));
+            }
+        }
+
+        let mut attributes = attributes;
+        attributes.sort_by(|a, b| {
+            let a_key = (a.name.namespace.as_deref().unwrap_or(""), &a.name.local_name);
+            let b_key = (b.name.namespace.as_deref().unwrap_or(""), &b.name.local_name);
+            a_key.cmp(&b_key)
+        });
+        for attribute in &attributes {
+            tag.push_str(&format!(" {}=\"{}\"", qualified_name(&attribute.name), escape_attribute(&attribute.value)));
+        }
+        tag.push('>');
+
+        self.write(&tag)
+    }
+
+    fn write(&mut self, s: &str) -> Result<()> {
+        self.writer.write_all(s.as_bytes()).map_err(error::io)
+    }
+}
+
+/// Normalizes line endings, and whitespace in attribute values, which the
+/// parser leaves as is. Character references are kept for the parser so
+/// that the characters they stand for are not normalized.
+fn normalize(input: &str) -> String {
+    let input = input.replace("\r\n", "\n").replace('\r', "\n");
+
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input.as_str();
+    while let Some(start) = rest.find('<') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let (markup, end) = match scan_markup(rest.as_bytes()) {
+            Some(markup) => markup,
+            // Unterminated markup is left for the parser to report
+            None => break,
+        };
+        match markup {
+            Markup::Other => output.push_str(&rest[..end]),
+            Markup::StartTag { .. } | Markup::EndTag => output.push_str(&normalize_tag(&rest[..end])),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn normalize_tag(tag: &str) -> String {
+    let mut quote = None;
+    tag.chars().map(|c| match (quote, c) {
+        (Some(q), _) if c == q => { quote = None; c },
+        (Some(_), '\t') | (Some(_), '\n') => ' ',
+        (Some(_), _) => c,
+        (None, '"') | (None, '\'') => { quote = Some(c); c },
+        (None, _) => c,
+    }).collect()
+}
+
+fn qualified_name(name: &OwnedName) -> String {
+    match name.prefix {
+        Some(ref prefix) => format!("{}:{}", prefix, name.local_name),
+        None => name.local_name.clone(),
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\r' => escaped.push_str("&#xD;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' => escaped.push_str("&#x9;"),
+            '\n' => escaped.push_str("&#xA;"),
+            '\r' => escaped.push_str("&#xD;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
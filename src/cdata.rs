@@ -0,0 +1,76 @@
+//! Text written as CDATA sections.
+//!
+//! Wrapping a field in `CData`, or pointing `serialize_with` at this module,
+//! writes its text as `<![CDATA[...]]>` instead of escaping markup
+//! characters. Any `]]>` in the text is split across two sections. Reading
+//! accepts both CDATA sections and escaped text:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate serde_xml;
+//! # use serde_xml::CData;
+//! #[derive(Serialize, Deserialize)]
+//! #[serde(rename = "page")]
+//! struct Page {
+//!     body: CData<String>,
+//!     #[serde(serialize_with = "serde_xml::cdata::serialize")]
+//!     script: String,
+//! }
+//!
+//! # fn main() {
+//! let page = Page { body: CData("<p>Hi</p>".to_string()), script: "a && b".to_string() };
+//! let xml = serde_xml::to_string_compact(&page).unwrap();
+//! assert!(xml.ends_with("<page><body><![CDATA[<p>Hi</p>]]></body><script><![CDATA[a && b]]></script></page>"));
+//! # }
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+/// Newtype name the serializer recognizes, other formats see the inner value.
+pub(crate) const NAME: &str = "$serde_xml::CData";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CData<T>(pub T);
+
+impl<T> From<T> for CData<T> {
+    fn from(value: T) -> Self {
+        CData(value)
+    }
+}
+
+impl<T> Deref for CData<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CData<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Serialize> Serialize for CData<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for CData<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(CData)
+    }
+}
+
+pub fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: ?Sized + Serialize,
+{
+    serializer.serialize_newtype_struct(NAME, value)
+}
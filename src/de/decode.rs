@@ -0,0 +1,365 @@
+use std::io::{self, Read};
+use std::ops::Range;
+
+use encoding_rs::{Decoder, DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use entities::EntityResolver;
+use limits::{Limit, Limits};
+
+use super::error::{self, Result};
+use super::expand::Expander;
+use super::scan::{declaration_end, find};
+
+/// How far to look for the end of the XML declaration.
+const DECLARATION_LIMIT: usize = 1024;
+
+/// Longest document type declaration to keep.
+const DOCTYPE_LIMIT: usize = 64 * 1024;
+
+/// Transcodes a document to UTF-8 before it reaches the parser.
+///
+/// Unless an encoding is forced, it is detected from the byte order mark,
+/// then from the `encoding` of the XML declaration, defaulting to UTF-8.
+///
+/// It also keeps the document type declaration, which the parser skips, and
+/// expands the entities it declares.
+pub struct DecodingReader<R: Read> {
+    inner: R,
+    forced: Option<&'static Encoding>,
+    decoder: Option<Decoder>,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    /// Output held back until the XML declaration is read whole.
+    head: Option<Vec<u8>>,
+    declared: Option<String>,
+    eof: bool,
+    done: bool,
+    prolog: Option<Vec<u8>>,
+    doctype: Option<String>,
+    expander: Expander,
+    limit: usize,
+    read: usize,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R) -> Self {
+        DecodingReader {
+            inner,
+            forced: None,
+            decoder: None,
+            input: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+            head: Some(Vec::new()),
+            declared: None,
+            eof: false,
+            done: false,
+            prolog: Some(Vec::new()),
+            doctype: None,
+            expander: Expander::new(),
+            limit: usize::MAX,
+            read: 0,
+        }
+    }
+
+    /// Decodes the document as `label` whatever it declares, for documents
+    /// that lie about their encoding.
+    pub fn with_encoding(inner: R, label: &str) -> Result<Self> {
+        let encoding = Encoding::for_label(label.trim().as_bytes())
+            .ok_or_else(|| error::with_message(format!("unsupported input encoding {}", label)))?;
+
+        let mut reader = Self::new(inner);
+        reader.forced = Some(encoding);
+        Ok(reader)
+    }
+
+    /// Contents of the `<!DOCTYPE ...>` declaration, once it has been read.
+    pub fn doctype(&self) -> Option<&str> {
+        self.doctype.as_deref()
+    }
+
+    /// The `encoding` of the XML declaration, which the parser is told is
+    /// UTF-8 instead.
+    pub fn declared_encoding(&self) -> Option<&str> {
+        self.declared.as_deref()
+    }
+
+    /// Expands `&name;` to the text `value` for each of `entities`, unless
+    /// the document declares an entity of the same name. Unlike the value of
+    /// a declared entity, `value` is not parsed as markup.
+    pub fn with_entities<I, K, V>(mut self, entities: I) -> Self
+        where I: IntoIterator<Item = (K, V)>, K: Into<String>, V: Into<String>
+    {
+        for (name, value) in entities {
+            self.expander.add(name.into(), &value.into());
+        }
+        self
+    }
+
+    /// Expands external entities, and reads the entity declarations of an
+    /// external DTD subset, with `resolver`. Without one, a reference to an
+    /// external entity fails reading.
+    pub fn with_entity_resolver<E: EntityResolver + 'static>(mut self, resolver: E) -> Self {
+        self.expander.resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Fails reading once more than `max_input_bytes` have been read, or
+    /// entity references expanded to more than `max_entity_expansion`.
+    pub(crate) fn set_limits(&mut self, limits: &Limits) {
+        self.limit = limits.max_input_bytes;
+        self.expander.limit = limits.max_entity_expansion;
+    }
+
+    pub(crate) fn limit_exceeded(&self) -> Option<Limit> {
+        if self.read > self.limit {
+            Some(Limit::InputBytes)
+        } else if self.expander.exceeded {
+            Some(Limit::EntityExpansion)
+        } else {
+            None
+        }
+    }
+
+    fn record(&mut self) -> io::Result<()> {
+        let complete = match self.prolog {
+            Some(ref mut prolog) => {
+                prolog.extend_from_slice(&self.output);
+                match scan_prolog(prolog) {
+                    Scan::Incomplete { doctype: true, start } if prolog.len() - start > DOCTYPE_LIMIT => {
+                        return Err(doctype_too_long());
+                    },
+                    Scan::Complete(Some(ref doctype)) if doctype.len() > DOCTYPE_LIMIT => {
+                        return Err(doctype_too_long());
+                    },
+                    Scan::Incomplete { start, .. } if !self.done => {
+                        // Only the markup not read whole yet is kept
+                        prolog.drain(..start);
+                        false
+                    },
+                    Scan::Incomplete { .. } => true,
+                    Scan::Complete(doctype) => {
+                        self.doctype = doctype;
+                        true
+                    },
+                }
+            },
+            None => false,
+        };
+        if complete {
+            self.prolog = None;
+        }
+        Ok(())
+    }
+
+    fn read_more(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8192];
+        let n = self.inner.read(&mut chunk)?;
+        self.read = self.read.saturating_add(n);
+        if self.read > self.limit {
+            return Err(invalid_data(format!("more than {} bytes of input", self.limit)));
+        }
+        if n == 0 {
+            self.eof = true;
+        }
+        self.input.extend_from_slice(&chunk[..n]);
+        Ok(n > 0)
+    }
+
+    fn sniff(&mut self) -> io::Result<&'static Encoding> {
+        while self.input.len() < 4 && self.read_more()? {}
+
+        if let Some((encoding, _)) = Encoding::for_bom(&self.input) {
+            return Ok(encoding);
+        }
+        if self.input.starts_with(b"<\0?\0") {
+            return Ok(UTF_16LE);
+        }
+        if self.input.starts_with(b"\0<\0?") {
+            return Ok(UTF_16BE);
+        }
+        if !self.input.starts_with(b"<?xml") {
+            return Ok(UTF_8);
+        }
+
+        while find(&self.input, b"?>").is_none() && self.input.len() < DECLARATION_LIMIT && self.read_more()? {}
+
+        let declared = declared_encoding(&self.input)
+            .map(|label| Encoding::for_label(label)
+                .ok_or_else(|| invalid_data(format!(
+                    "unsupported input encoding {}", String::from_utf8_lossy(label)))))
+            .transpose()?;
+
+        match declared {
+            // A UTF-16 document starts with a byte order mark, so this one lies
+            Some(encoding) if encoding == UTF_16LE || encoding == UTF_16BE => Ok(UTF_8),
+            Some(encoding) => Ok(encoding),
+            None => Ok(UTF_8),
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        if self.decoder.is_none() {
+            let encoding = match self.forced {
+                Some(encoding) => encoding,
+                None => self.sniff()?,
+            };
+            self.decoder = Some(encoding.new_decoder_with_bom_removal());
+        }
+
+        if self.input.is_empty() && !self.eof {
+            self.read_more()?;
+        }
+
+        let decoder = self.decoder.as_mut().expect("decoder set above");
+        self.output.resize(8192, 0);
+        self.position = 0;
+
+        let (result, read, written) = decoder.decode_to_utf8_without_replacement(
+            &self.input, &mut self.output, self.eof);
+        self.input.drain(..read);
+        self.output.truncate(written);
+
+        match result {
+            DecoderResult::InputEmpty => self.done = self.eof,
+            DecoderResult::OutputFull => {},
+            DecoderResult::Malformed(..) => {
+                let encoding = decoder.encoding().name();
+                return Err(invalid_data(format!("malformed {} input", encoding)));
+            },
+        }
+        Ok(())
+    }
+
+    /// Makes the XML declaration declare the UTF-8 the document has been
+    /// decoded to, which the parser would otherwise decode once more. Until
+    /// the declaration has been read whole, the output is held back and this
+    /// returns false.
+    fn declare_utf8(&mut self) -> bool {
+        let mut head = match self.head.take() {
+            Some(head) => head,
+            None => return true,
+        };
+        head.extend_from_slice(&self.output);
+
+        let declaration = head.len() < 6 && b"<?xml ".starts_with(&head)
+            || head.starts_with(b"<?xml") && head[5].is_ascii_whitespace();
+        if declaration && find(&head, b"?>").is_none() && !self.done && head.len() < DECLARATION_LIMIT {
+            self.output.clear();
+            self.head = Some(head);
+            return false;
+        }
+
+        if declaration {
+            if let Some(label) = encoding_label(&head) {
+                self.declared = Some(String::from_utf8_lossy(&head[label.clone()]).into_owned());
+                head.splice(label, b"UTF-8".iter().cloned());
+            }
+        }
+        self.output = head;
+        self.position = 0;
+        true
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.position < self.output.len() {
+                let n = buf.len().min(self.output.len() - self.position);
+                buf[..n].copy_from_slice(&self.output[self.position..self.position + n]);
+                self.position += n;
+                return Ok(n);
+            }
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+            if !self.declare_utf8() {
+                continue;
+            }
+            self.record()?;
+            self.expander.push(&mut self.output, self.done)?;
+        }
+    }
+}
+
+enum Scan {
+    /// The input ends in the markup starting at `start`, which is a document
+    /// type declaration if `doctype` is set.
+    Incomplete { start: usize, doctype: bool },
+    Complete(Option<String>),
+}
+
+/// Looks for a document type declaration among the markup preceding the
+/// root element.
+fn scan_prolog(input: &[u8]) -> Scan {
+    let mut rest = input;
+    loop {
+        rest = trim_start(rest);
+        let start = input.len() - rest.len();
+        if rest.is_empty() {
+            return Scan::Incomplete { start, doctype: false };
+        }
+
+        let end = if rest.starts_with(b"<?") {
+            find(rest, b"?>").map(|end| end + 2)
+        } else if rest.starts_with(b"<!--") {
+            find(rest, b"-->").map(|end| end + 3)
+        } else if rest.starts_with(b"<!DOCTYPE") {
+            return match declaration_end(rest) {
+                Some(end) => {
+                    let doctype = String::from_utf8_lossy(&rest[9..end - 1]).trim().to_string();
+                    Scan::Complete(Some(doctype))
+                },
+                None => Scan::Incomplete { start, doctype: true },
+            };
+        } else if rest.len() < 9 && (b"<!DOCTYPE".starts_with(rest) || b"<!--".starts_with(rest)) {
+            None
+        } else {
+            return Scan::Complete(None);
+        };
+
+        match end {
+            Some(end) => rest = &rest[end..],
+            None => return Scan::Incomplete { start, doctype: b"<!DOCTYPE".starts_with(rest) },
+        }
+    }
+}
+
+/// Extracts the `encoding` pseudo-attribute of an XML declaration.
+pub(super) fn declared_encoding(input: &[u8]) -> Option<&[u8]> {
+    encoding_label(input).map(|label| &input[label])
+}
+
+/// Where the `encoding` pseudo-attribute of an XML declaration is.
+fn encoding_label(input: &[u8]) -> Option<Range<usize>> {
+    let end = find(input, b"?>")?;
+    let declaration = &input[..end];
+
+    let start = find(declaration, b"encoding")? + 8;
+    let rest = trim_start(&declaration[start..]);
+    let rest = trim_start(rest.strip_prefix(b"=")?);
+
+    let quote = *rest.first()?;
+    if quote != b'"' && quote != b'\'' {
+        return None;
+    }
+    let start = declaration.len() - rest.len() + 1;
+    let len = rest[1..].iter().position(|&b| b == quote)?;
+    Some(start..start + len)
+}
+
+fn doctype_too_long() -> io::Error {
+    invalid_data(format!("document type declaration longer than {} bytes", DOCTYPE_LIMIT))
+}
+
+fn trim_start(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
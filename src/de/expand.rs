@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+
+use entities::EntityResolver;
+
+use super::scan::{find, scan_markup, subset_start, Markup};
+
+/// Longest entity name looked for after a `&`.
+const NAME_LIMIT: usize = 256;
+
+/// How deep entities may refer to other entities.
+const NESTING_LIMIT: usize = 16;
+
+const PREDEFINED: [&str; 5] = ["lt", "gt", "amp", "apos", "quot"];
+
+enum Entity {
+    Internal(String),
+    External { public_id: Option<String>, system_id: String },
+}
+
+/// Replaces references to declared entities in the decoded document, as the
+/// parser only knows the predefined ones, and hands it the document type
+/// declaration without its internal subset, which it cannot skip reliably.
+pub(crate) struct Expander {
+    declared: HashMap<String, Entity>,
+    extra: HashMap<String, String>,
+    pub(crate) resolver: Option<Box<dyn EntityResolver>>,
+    pending: Vec<u8>,
+    /// Set once past the root start tag with nothing to expand.
+    passthrough: bool,
+    expanded: usize,
+    pub(crate) limit: usize,
+    pub(crate) exceeded: bool,
+}
+
+impl Expander {
+    pub fn new() -> Self {
+        Expander {
+            declared: HashMap::new(),
+            extra: HashMap::new(),
+            resolver: None,
+            pending: Vec::new(),
+            passthrough: false,
+            expanded: 0,
+            limit: usize::MAX,
+            exceeded: false,
+        }
+    }
+
+    /// Has `&name;` stand for `value` as literal text, markup characters
+    /// included.
+    pub fn add(&mut self, name: String, value: &str) {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                c => escaped.push(c),
+            }
+        }
+        self.extra.insert(name, escaped);
+    }
+
+    /// Expands what it can of `chunk` in place, keeping back an incomplete
+    /// reference or markup until the next one, or until `eof`.
+    pub fn push(&mut self, chunk: &mut Vec<u8>, eof: bool) -> io::Result<()> {
+        if self.passthrough && self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut input = mem::take(&mut self.pending);
+        input.extend_from_slice(chunk);
+
+        let mut output = Vec::with_capacity(input.len());
+        let mut i = 0;
+        while i < input.len() && !self.passthrough {
+            let rest = &input[i..];
+            match rest[0] {
+                b'<' => {
+                    let (markup, len) = match scan_markup(rest) {
+                        Some(markup) => markup,
+                        None if eof => (Markup::Other, rest.len()),
+                        None => break,
+                    };
+                    let markup_bytes = &rest[..len];
+                    match markup {
+                        Markup::StartTag { .. } => {
+                            self.expand_tag(markup_bytes, &mut output)?;
+                            self.passthrough = self.declared.is_empty() && self.extra.is_empty();
+                        },
+                        Markup::Other if markup_bytes.starts_with(b"<!DOCTYPE") => {
+                            self.declare(markup_bytes)?;
+                            output.extend_from_slice(&without_subset(markup_bytes));
+                        },
+                        _ => output.extend_from_slice(markup_bytes),
+                    }
+                    i += len;
+                },
+                b'&' => match reference(rest) {
+                    Some(name) => {
+                        let len = name.len() + 2;
+                        match self.replacement(name)? {
+                            Some(text) => output.extend_from_slice(text.as_bytes()),
+                            None => output.extend_from_slice(&rest[..len]),
+                        }
+                        i += len;
+                    },
+                    None if !eof && rest.len() < NAME_LIMIT + 2 && !rest[1..].contains(&b'<') => break,
+                    None => {
+                        output.push(b'&');
+                        i += 1;
+                    },
+                },
+                _ => {
+                    let len = rest.iter().position(|&b| b == b'<' || b == b'&').unwrap_or(rest.len());
+                    output.extend_from_slice(&rest[..len]);
+                    i += len;
+                },
+            }
+        }
+        if self.passthrough {
+            output.extend_from_slice(&input[i..]);
+        } else {
+            self.pending = input.split_off(i);
+        }
+        *chunk = output;
+        Ok(())
+    }
+
+    /// Copies a start tag, expanding references in its attribute values.
+    fn expand_tag(&mut self, tag: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+        let mut quote = None;
+        let mut i = 0;
+        while i < tag.len() {
+            let b = tag[i];
+            match (quote, b) {
+                (Some(_), b'&') => {
+                    if let Some(name) = reference(&tag[i..]) {
+                        if let Some(text) = self.replacement(name)? {
+                            escape_attribute(&text, output);
+                            i += name.len() + 2;
+                            continue;
+                        }
+                    }
+                },
+                (Some(q), _) if b == q => quote = None,
+                (None, b'"') | (None, b'\'') => quote = Some(b),
+                _ => {},
+            }
+            output.push(b);
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Text to replace `&name;` with, if it is an entity other than a
+    /// predefined one.
+    fn replacement(&mut self, name: &str) -> io::Result<Option<String>> {
+        if name.starts_with('#') || PREDEFINED.contains(&name) {
+            return Ok(None);
+        }
+        self.expand(name, 0)
+    }
+
+    fn expand(&mut self, name: &str, depth: usize) -> io::Result<Option<String>> {
+        if depth > NESTING_LIMIT {
+            return Err(invalid_data(format!("entity {} is nested too deeply or recursive", name)));
+        }
+        let value = match self.declared.get(name) {
+            Some(Entity::Internal(value)) => value.clone(),
+            Some(Entity::External { public_id, system_id }) => {
+                let value = match self.resolver {
+                    Some(ref resolver) => resolver.resolve(public_id.as_deref(), system_id)?,
+                    None => return Err(invalid_data(format!(
+                        "external entity {} is not expanded without an EntityResolver", name))),
+                };
+                let value = without_text_declaration(&value).to_string();
+                self.declared.insert(name.to_string(), Entity::Internal(value.clone()));
+                value
+            },
+            None => match self.extra.get(name) {
+                Some(value) => value.clone(),
+                None => return Ok(None),
+            },
+        };
+
+        let mut text = String::with_capacity(value.len());
+        let mut rest = &value[..];
+        while let Some(start) = rest.find('&') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let nested = match reference(rest.as_bytes()) {
+                Some(nested) if !nested.starts_with('#') && !PREDEFINED.contains(&nested) => {
+                    self.expand(nested, depth + 1)?.map(|value| (nested.len() + 2, value))
+                },
+                _ => None,
+            };
+            match nested {
+                Some((len, value)) => {
+                    text.push_str(&value);
+                    rest = &rest[len..];
+                },
+                None => {
+                    text.push('&');
+                    rest = &rest[1..];
+                },
+            }
+        }
+        text.push_str(rest);
+
+        self.expanded = self.expanded.saturating_add(text.len());
+        if self.expanded > self.limit {
+            self.exceeded = true;
+            return Err(invalid_data(format!("more than {} bytes of expanded entities", self.limit)));
+        }
+        Ok(Some(text))
+    }
+
+    /// Records the entities declared by a document type declaration, those
+    /// of the internal subset taking precedence over the external subset.
+    fn declare(&mut self, doctype: &[u8]) -> io::Result<()> {
+        let doctype = String::from_utf8_lossy(doctype);
+        let end = doctype.len() - 1;
+        let (head, subset) = match subset_start(doctype.as_bytes()) {
+            Some(start) => (&doctype[..start], &doctype[start + 1..doctype[..end].rfind(']').unwrap_or(end)]),
+            None => (&doctype[..end], ""),
+        };
+        self.declare_all(subset);
+
+        let external = match self.resolver {
+            Some(ref resolver) => match external_id(skip_name(&head["<!DOCTYPE".len()..])) {
+                Some((public_id, system_id, _)) => Some(resolver.resolve(public_id.as_deref(), &system_id)?),
+                None => None,
+            },
+            None => None,
+        };
+        if let Some(external) = external {
+            self.declare_all(without_text_declaration(&external));
+        }
+        Ok(())
+    }
+
+    fn declare_all(&mut self, subset: &str) {
+        let mut rest = subset;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start..];
+            let end = match scan_markup(rest.as_bytes()) {
+                Some((_, end)) => end,
+                None => return,
+            };
+            if let Some(declaration) = rest[..end].strip_prefix("<!ENTITY") {
+                if let Some((name, entity)) = entity(declaration) {
+                    // The first declaration of an entity is binding
+                    self.declared.entry(name).or_insert(entity);
+                }
+            }
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// Name of the reference `input` starts with, if it is complete.
+fn reference(input: &[u8]) -> Option<&str> {
+    let end = input.iter().take(NAME_LIMIT + 2).position(|&b| b == b';')?;
+    let name = &input[1..end];
+    if name.is_empty() || name.iter().any(|&b| b.is_ascii_whitespace() || b == b'<' || b == b'&') {
+        return None;
+    }
+    std::str::from_utf8(name).ok()
+}
+
+/// Parses what follows `<!ENTITY` in a general entity declaration, leaving
+/// out parameter and unparsed entities.
+fn entity(declaration: &str) -> Option<(String, Entity)> {
+    let declaration = declaration.trim_start();
+    if declaration.starts_with('%') {
+        return None;
+    }
+    let name_end = declaration.find(char::is_whitespace)?;
+    let name = declaration[..name_end].to_string();
+    let rest = skip_name(declaration);
+
+    if let Some((value, _)) = literal(rest) {
+        return Some((name, Entity::Internal(value.to_string())));
+    }
+    let (public_id, system_id, rest) = external_id(rest)?;
+    if rest.trim_start().starts_with("NDATA") {
+        return None;
+    }
+    Some((name, Entity::External { public_id, system_id }))
+}
+
+/// What follows the name `input` starts with.
+fn skip_name(input: &str) -> &str {
+    let input = input.trim_start();
+    input[input.find(char::is_whitespace).unwrap_or(input.len())..].trim_start()
+}
+
+/// Public and system identifiers of `SYSTEM "uri"` or `PUBLIC "id" "uri"`,
+/// and what follows them.
+fn external_id(input: &str) -> Option<(Option<String>, String, &str)> {
+    if let Some(rest) = input.strip_prefix("SYSTEM") {
+        let (system_id, rest) = literal(rest.trim_start())?;
+        Some((None, system_id.to_string(), rest))
+    } else {
+        let rest = input.strip_prefix("PUBLIC")?;
+        let (public_id, rest) = literal(rest.trim_start())?;
+        let (system_id, rest) = literal(rest.trim_start())?;
+        Some((Some(public_id.to_string()), system_id.to_string(), rest))
+    }
+}
+
+/// Quoted literal `input` starts with, and what follows it.
+fn literal(input: &str) -> Option<(&str, &str)> {
+    let quote = input.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let end = input[1..].find(quote)? + 1;
+    Some((&input[1..end], &input[end + 1..]))
+}
+
+fn without_subset(doctype: &[u8]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(doctype);
+    match subset_start(text.as_bytes()) {
+        Some(start) => format!("{}>", text[..start].trim_end()).into_bytes(),
+        None => doctype.to_vec(),
+    }
+}
+
+/// Text of an external parsed entity, without its byte order mark and text
+/// declaration.
+fn without_text_declaration(text: &str) -> &str {
+    let text = text.trim_start_matches('\u{FEFF}');
+    if text.starts_with("<?xml") && text[5..].starts_with(|c: char| c.is_whitespace()) {
+        if let Some(end) = find(text.as_bytes(), b"?>") {
+            return &text[end + 2..];
+        }
+    }
+    text
+}
+
+fn escape_attribute(text: &str, output: &mut Vec<u8>) {
+    for c in text.chars() {
+        match c {
+            '<' => output.extend_from_slice(b"&lt;"),
+            '"' => output.extend_from_slice(b"&quot;"),
+            '\'' => output.extend_from_slice(b"&apos;"),
+            c => output.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
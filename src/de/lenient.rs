@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Display};
+use std::io::Read;
+
+use serde::de::{Deserialize, DeserializeOwned};
+
+use xml::attribute::OwnedAttribute;
+use xml::common::TextPosition;
+use xml::reader::XmlEvent;
+
+use super::error::{self, Error, Result};
+use super::{qualified_name_from, Deserializer};
+
+/// An error `from_str_lenient` and `from_reader_lenient` recovered from by
+/// skipping what it was about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Line of the element, or of the element carrying the attribute,
+    /// starting at 1.
+    pub line: u64,
+    /// Column of the element, starting at 1.
+    pub column: u64,
+    /// Qualified name of the element, or `@` followed by that of the
+    /// attribute, that was skipped.
+    pub name: String,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}: {}", self.line, self.column, self.name, self.message)
+    }
+}
+
+/// Element, by its position in document order, or attribute of one, to leave
+/// out of the document.
+type Skip = (usize, Option<String>);
+
+/// State of a lenient read, see `from_str_lenient`.
+///
+/// Values that do not convert are read as their default in place, so that
+/// the rest of the value is still checked, then the recorded events of the
+/// innermost item of a sequence holding what was skipped are deserialized
+/// again without it. Only what is outside of any item has the whole document
+/// read again.
+pub(crate) struct Lenient {
+    skipped: BTreeMap<Skip, (Diagnostic, Error)>,
+    /// Checkpoint to deserialize again from, by its level, the start of the
+    /// document being 0.
+    restart: Option<usize>,
+    /// Elements of the items being deserialized, see `item`.
+    checkpoints: Vec<usize>,
+    /// Events read from the document, and how many of them were read again.
+    events: Vec<(XmlEvent, TextPosition)>,
+    replayed: usize,
+    position: TextPosition,
+    /// Elements read but not taken yet, with their start positions.
+    read: VecDeque<(usize, TextPosition)>,
+    /// Elements taken and not closed yet.
+    open: Vec<(usize, TextPosition, String)>,
+    /// Attribute whose value or name is being deserialized.
+    pub attribute: Option<String>,
+}
+
+impl Lenient {
+    fn new() -> Self {
+        Lenient {
+            skipped: BTreeMap::new(),
+            restart: None,
+            checkpoints: Vec::new(),
+            events: Vec::new(),
+            replayed: 0,
+            position: TextPosition::new(),
+            read: VecDeque::new(),
+            open: Vec::new(),
+            attribute: None,
+        }
+    }
+
+    /// Starts reading the recorded events again.
+    pub fn rewind(&mut self) {
+        self.restart = None;
+        self.replayed = 0;
+        self.read.clear();
+        self.open.clear();
+        self.attribute = None;
+    }
+
+    /// Next event to read again, if any.
+    pub fn replay(&mut self) -> Option<XmlEvent> {
+        let (event, position) = self.events.get(self.replayed)?.clone();
+        self.replayed += 1;
+        self.position = position;
+        Some(event)
+    }
+
+    /// Keeps an event read from the document to read it again.
+    pub fn record(&mut self, event: &XmlEvent, position: TextPosition) {
+        self.events.push((event.clone(), position));
+        self.replayed = self.events.len();
+        self.position = position;
+    }
+
+    /// Leaves out skipped attributes of an event just read, returning
+    /// whether to skip the element it starts.
+    pub fn filter(&mut self, event: &mut XmlEvent, index: usize) -> bool {
+        if let XmlEvent::StartElement { ref mut attributes, .. } = *event {
+            if self.skipped.contains_key(&(index, None)) {
+                return true;
+            }
+            self.filter_attributes(attributes, index);
+            self.read.push_back((index, self.position));
+        }
+        false
+    }
+
+    fn filter_attributes(&self, attributes: &mut Vec<OwnedAttribute>, index: usize) {
+        attributes.retain(|OwnedAttribute { name, .. }| {
+            !self.skipped.contains_key(&(index, Some(format!("@{}", qualified_name_from(name)))))
+        });
+    }
+
+    /// Follows the elements as the deserializer takes their events.
+    pub fn take(&mut self, event: &XmlEvent) {
+        match *event {
+            XmlEvent::StartElement { ref name, .. } => {
+                let (index, position) = self.read.pop_front().expect("filtered when read");
+                self.open.push((index, position, qualified_name_from(name)));
+            },
+            XmlEvent::EndElement { .. } => {
+                self.open.pop();
+            },
+            _ => {},
+        }
+    }
+
+    /// Skips `attribute`, or the innermost element if `None`, for a
+    /// recoverable error, which is returned otherwise.
+    pub fn skip(&mut self, err: Error, attribute: Option<String>) -> Result<()> {
+        if !err.is_recoverable() {
+            return Err(err);
+        }
+        let (index, position, element) = match self.open.last() {
+            Some(open) => open.clone(),
+            None => return Err(err),
+        };
+        let skip = (index, attribute.clone());
+        if (index == 1 && attribute.is_none()) || self.skipped.contains_key(&skip) {
+            return Err(err);
+        }
+        let diagnostic = Diagnostic {
+            line: position.row + 1,
+            column: position.column + 1,
+            name: attribute.unwrap_or(element),
+            message: err.to_string(),
+        };
+        self.skipped.insert(skip, (diagnostic, err));
+        Ok(())
+    }
+
+    /// Skips what `err` is about and has the innermost value holding it read
+    /// again, for a value read as its default instead.
+    pub fn substitute(&mut self, err: Error, attribute: Option<String>) -> Result<()> {
+        // An element is left out of the value of its parent, an attribute
+        // out of that of its own element
+        let innermost = self.open.last().map(|&(index, ..)| index);
+        let level = match self.checkpoints.last() {
+            Some(&element) if attribute.is_none() && Some(element) == innermost => self.checkpoints.len() - 1,
+            _ => self.checkpoints.len(),
+        };
+        self.skip(err, attribute)?;
+        self.restart = Some(self.restart.map_or(level, |restart| restart.min(level)));
+        Ok(())
+    }
+
+    /// Takes the outcome of deserializing the value at checkpoint `level`,
+    /// which is `None` if it has to be deserialized again.
+    fn settle<T>(&mut self, result: Result<T>, level: usize) -> Result<Option<T>> {
+        let mut err = match result {
+            Ok(value) => return Ok(if self.restart == Some(level) { None } else { Some(value) }),
+            Err(err) => err,
+        };
+        // A required field was skipped, report why
+        if let Some(original) = err.missing_field().and_then(|name| self.skipped_field(name)) {
+            return Err(original);
+        }
+        if self.restart.is_none() {
+            let attribute = self.attribute.take();
+            let message = err.to_string();
+            self.substitute(err, attribute)?;
+            // Only ends the values up to the one read again
+            err = error::with_message(message);
+        }
+        if self.restart == Some(level) {
+            Ok(None)
+        } else {
+            Err(err)
+        }
+    }
+
+    /// Takes the error that had a required field skipped.
+    fn skipped_field(&mut self, name: &str) -> Option<Error> {
+        let skip = self.skipped.iter()
+            .find(|(_, (diagnostic, _))| diagnostic.name == name)
+            .map(|(skip, _)| skip.clone())?;
+        self.skipped.remove(&skip).map(|(_, err)| err)
+    }
+
+    fn diagnostics(self) -> Vec<Diagnostic> {
+        self.skipped.into_iter().map(|(_, (diagnostic, _))| diagnostic).collect()
+    }
+}
+
+/// Deserializes a document, skipping what the recoverable errors are about,
+/// and reads the recorded events again while anything outside of the items
+/// of sequences had to be skipped in a way the value could not follow.
+pub fn deserialize<R: Read, T: DeserializeOwned>(mut de: Deserializer<R>) -> Result<(T, Vec<Diagnostic>)> {
+    de.lenient = Some(Lenient::new());
+    loop {
+        let result = T::deserialize(&mut de);
+        if let Some(value) = de.lenient.as_mut().expect("set above").settle(result, 0)? {
+            let lenient = de.lenient.take().expect("set above");
+            return Ok((value, lenient.diagnostics()));
+        }
+        de.restart();
+    }
+}
+
+/// Deserializes the item of a sequence whose start tag was just taken, and
+/// when reading leniently, reads it again from there while anything in it had
+/// to be skipped, rather than the whole document.
+pub fn item<'de, R: Read, T: Deserialize<'de>>(de: &mut Deserializer<R>) -> Result<T> {
+    let lenient = match de.lenient {
+        Some(ref mut lenient) if de.lookahead.is_none() && de.raw_lookahead.is_none() => lenient,
+        _ => return T::deserialize(de),
+    };
+    let element = lenient.open.last().map_or(0, |&(index, ..)| index);
+    lenient.checkpoints.push(element);
+    let level = lenient.checkpoints.len();
+    let (replayed, position, read, open) = (lenient.replayed, lenient.position, lenient.read.clone(), lenient.open.clone());
+    let (tag_name, attributes, comments) = (de.tag_name.clone(), de.attributes.clone(), de.comments.clone());
+    let (depth, taken, elements) = (de.depth, de.open, de.elements);
+    loop {
+        let result = T::deserialize(&mut *de);
+        let lenient = de.lenient.as_mut().expect("checked above");
+        match lenient.settle(result, level) {
+            Ok(Some(value)) => {
+                lenient.checkpoints.pop();
+                return Ok(value);
+            },
+            Ok(None) => {},
+            Err(err) => {
+                lenient.checkpoints.pop();
+                return Err(err);
+            },
+        }
+        lenient.restart = None;
+        lenient.replayed = replayed;
+        lenient.position = position;
+        lenient.read = read.clone();
+        lenient.open = open.clone();
+        lenient.attribute = None;
+        de.attributes = attributes.clone().and_then(|mut attributes| {
+            lenient.filter_attributes(&mut attributes, element);
+            if attributes.is_empty() { None } else { Some(attributes) }
+        });
+        de.lookahead = None;
+        de.raw_lookahead = None;
+        de.tag_name = tag_name.clone();
+        de.comments = comments.clone();
+        de.depth = depth;
+        de.open = taken;
+        de.elements = elements;
+    }
+}
@@ -1,6 +1,7 @@
 use std::io::Read;
 
-use serde::de::IntoDeserializer;
+use serde::de::value::SeqDeserializer;
+use serde::de::{IntoDeserializer, Visitor};
 
 use xml::name::OwnedName;
 use xml::attribute::OwnedAttribute;
@@ -9,21 +10,102 @@ use xml::reader::XmlEvent;
 use super::{Deserializer, qualified_name_from};
 use super::plain::PlainStringDeserializer;
 use super::super::error::{self, Error, Result};
+use super::super::lexical::ValueFormat;
 
 pub struct MapAccess<'a, R: 'a + Read> {
     de: &'a mut Deserializer<R>,
+    /// Those of a struct, to skip the others when reading leniently.
+    fields: Option<&'static [&'static str]>,
     attributes: std::vec::IntoIter<OwnedAttribute>,
     value: Option<String>,
+    attribute: Option<String>,
     end_tag: Option<OwnedName>,
+    /// Depth of the element, whose comments are passed once its content has
+    /// been read.
+    depth: usize,
+    comments: Option<Vec<String>>,
+    comments_taken: bool,
 }
 
 impl<'a, R: 'a + Read> MapAccess<'a, R> {
-    pub fn new(de: &'a mut Deserializer<R>, attributes: Vec<OwnedAttribute>) -> Self {
+    pub fn new(
+        de: &'a mut Deserializer<R>, attributes: Vec<OwnedAttribute>, fields: Option<&'static [&'static str]>
+    ) -> Self {
+        let depth = de.open;
         MapAccess {
             de,
+            fields,
             attributes: attributes.into_iter(),
             value: None,
-            end_tag: None
+            attribute: None,
+            end_tag: None,
+            depth,
+            comments: None,
+            comments_taken: false,
+        }
+    }
+
+    /// Tells a lenient read which attribute `err` is about.
+    fn failed(&mut self, err: Error) -> Error {
+        if let Some(ref mut lenient) = self.de.lenient {
+            lenient.attribute = self.attribute.take();
+        }
+        err
+    }
+
+    /// Whether a lenient read skips `name` as an unknown field, in which
+    /// case it has been reported.
+    fn skips(&mut self, name: &str, attribute: bool) -> Result<bool> {
+        let (fields, lenient) = match (self.fields, self.de.lenient.as_mut()) {
+            (Some(fields), Some(lenient)) if !fields.contains(&name) => (fields, lenient),
+            _ => return Ok(false),
+        };
+        let err = <Error as serde::de::Error>::unknown_field(name, fields);
+        lenient.skip(err, if attribute { Some(name.to_string()) } else { None })?;
+        Ok(true)
+    }
+
+    /// Whether the comments of the element are to be passed to its value.
+    fn wants_comments(&self) -> bool {
+        let field = match self.fields {
+            Some(fields) => fields.contains(&"#comment"),
+            None => true,
+        };
+        self.de.capture_comments && !self.comments_taken && field
+    }
+
+    fn next_element_key<'de, K: serde::de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let wants_comments = self.wants_comments();
+        match self.de.peek()? {
+            XmlEvent::EndElement { .. } | XmlEvent::EndDocument if wants_comments => {
+                self.comments_taken = true;
+                let comments = self.de.take_comments(self.depth)?;
+                if comments.is_empty() {
+                    return Ok(None);
+                }
+                self.comments = Some(comments);
+                seed.deserialize("#comment".into_deserializer()).map(Some)
+            },
+            XmlEvent::EndElement { .. } | XmlEvent::EndDocument => Ok(None),
+            XmlEvent::Characters { .. } => {
+                self.value = Some(self.de.characters()?);
+                seed.deserialize(".".into_deserializer()).map(Some)
+            },
+            XmlEvent::StartElement { .. } => {
+                let (tag_name, attributes) = self.de.start_tag()?;
+                if self.skips(&qualified_name_from(&tag_name), false)? {
+                    self.de.skip_element()?;
+                    return self.next_element_key(seed);
+                }
+                self.de.tag_name = Some(tag_name.clone());
+                self.de.put_attributes(attributes);
+                self.end_tag = Some(tag_name.clone());
+
+                let qualified_tag = qualified_name_from(&tag_name);
+                trace!("found subtag {}", qualified_tag);
+                seed.deserialize(qualified_tag.into_deserializer()).map(Some)
+            },
+            _ => Err(error::with_message(format!("expected map key, found {:?}", self.de.next()?))),
         }
     }
 }
@@ -35,34 +117,33 @@ impl<'a, 'de, R: 'a + Read> serde::de::MapAccess<'de> for MapAccess<'a, R> {
         match self.attributes.next() {
             Some(OwnedAttribute { name, value }) => {
                 trace!("found attribute {} {}", name, value);
-                self.value = Some(value);
                 let attribute_name = format!("@{}", qualified_name_from(&name));
-                seed.deserialize(attribute_name.into_deserializer()).map(Some)
+                if self.skips(&attribute_name, true)? {
+                    return self.next_key_seed(seed);
+                }
+                self.value = Some(value);
+                self.attribute = Some(attribute_name.clone());
+                seed.deserialize(attribute_name.into_deserializer()).map(Some).map_err(|err| self.failed(err))
             },
-            None => match self.de.peek()? {
-                XmlEvent::EndElement { .. } | XmlEvent::EndDocument => Ok(None),
-                XmlEvent::Characters { .. } => {
-                    self.value = Some(self.de.characters()?);
-                    seed.deserialize(".".into_deserializer()).map(Some)
-                },
-                XmlEvent::StartElement { .. } => {
-                    let (tag_name, attributes) = self.de.start_tag()?;
-                    self.de.tag_name = Some(tag_name.clone());
-                    self.de.put_attributes(attributes);
-                    self.end_tag = Some(tag_name.clone());
-
-                    let qualified_tag = qualified_name_from(&tag_name);
-                    trace!("found subtag {}", qualified_tag);
-                    seed.deserialize(qualified_tag.into_deserializer()).map(Some)
-                },
-                _ => Err(error::with_message(format!("expected map key, found {:?}", self.de.next()?))),
+            None => {
+                self.attribute = None;
+                self.next_element_key(seed)
             },
         }
     }
 
     fn next_value_seed<V: serde::de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        if let Some(comments) = self.comments.take() {
+            return seed.deserialize(CommentsDeserializer(comments));
+        }
         match self.value.take() {
-            Some(v) => seed.deserialize(PlainStringDeserializer(v)),
+            Some(v) => {
+                let mut de = PlainStringDeserializer::new(v, self.de.format);
+                if let Some(ref mut lenient) = self.de.lenient {
+                    de = de.with_lenient(lenient, self.attribute.clone());
+                }
+                seed.deserialize(de).map_err(|err| self.failed(err))
+            },
             None => {
                 let v = seed.deserialize(&mut *self.de)?;
                 let _ = self.de.end_tag(&self.end_tag.take().unwrap());
@@ -71,3 +152,199 @@ impl<'a, 'de, R: 'a + Read> serde::de::MapAccess<'de> for MapAccess<'a, R> {
         }
     }
 }
+
+/// Comments of an element, as a single string or as a sequence.
+struct CommentsDeserializer(Vec<String>);
+
+impl<'de> serde::de::Deserializer<'de> for CommentsDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0.join("\n"))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut seq = SeqDeserializer::new(self.0.into_iter());
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Reads `<entry key="...">value</entry>` elements, see
+/// `MapRepresentation::Entries`.
+///
+/// A struct with flattened fields is read as a map too, but it asks for its
+/// keys as identifiers, so it is given the names of elements and attributes
+/// instead, as by `MapAccess`.
+pub struct EntryAccess<'a, R: 'a + Read> {
+    de: &'a mut Deserializer<R>,
+    element: String,
+    key: String,
+    attributes: std::vec::IntoIter<OwnedAttribute>,
+    /// Whether the keys are struct fields, once the first one is read.
+    fields: Option<bool>,
+    value: Option<String>,
+    end_tag: Option<OwnedName>,
+}
+
+impl<'a, R: 'a + Read> EntryAccess<'a, R> {
+    pub fn new(de: &'a mut Deserializer<R>, attributes: Vec<OwnedAttribute>, element: String, key: String) -> Self {
+        EntryAccess {
+            de,
+            element,
+            key,
+            attributes: attributes.into_iter(),
+            fields: None,
+            value: None,
+            end_tag: None,
+        }
+    }
+}
+
+impl<'a, 'de, R: 'a + Read> serde::de::MapAccess<'de> for EntryAccess<'a, R> {
+    type Error = Error;
+
+    fn next_key_seed<K: serde::de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.de.peek()? {
+            // The attributes of a map element are not entries
+            XmlEvent::EndElement { .. } | XmlEvent::EndDocument if self.fields != Some(true) => return Ok(None),
+            XmlEvent::EndElement { .. } | XmlEvent::EndDocument => {
+                return match self.attributes.next() {
+                    Some(OwnedAttribute { name, value }) => {
+                        self.value = Some(value);
+                        seed.deserialize(format!("@{}", qualified_name_from(&name)).into_deserializer()).map(Some)
+                    },
+                    None => Ok(None),
+                };
+            },
+            XmlEvent::StartElement { .. } => {},
+            _ => return Err(error::with_message(format!("expected map entry, found {:?}", self.de.next()?))),
+        }
+
+        let (tag_name, mut attributes) = self.de.start_tag()?;
+        let key = seed.deserialize(EntryKey {
+            element: &self.element,
+            key: &self.key,
+            tag_name: &tag_name,
+            attributes: &mut attributes,
+            fields: &mut self.fields,
+            format: self.de.format,
+        })?;
+
+        self.de.tag_name = Some(tag_name.clone());
+        self.de.put_attributes(attributes);
+        self.end_tag = Some(tag_name);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: serde::de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        if let Some(value) = self.value.take() {
+            return seed.deserialize(PlainStringDeserializer::new(value, self.de.format));
+        }
+        let v = seed.deserialize(&mut *self.de)?;
+        self.de.end_tag(&self.end_tag.take().unwrap())?;
+        Ok(v)
+    }
+}
+
+/// Key of an entry, or name of a struct field if asked for as an identifier.
+struct EntryKey<'b> {
+    element: &'b str,
+    key: &'b str,
+    tag_name: &'b OwnedName,
+    attributes: &'b mut Vec<OwnedAttribute>,
+    fields: &'b mut Option<bool>,
+    format: ValueFormat,
+}
+
+impl<'b> EntryKey<'b> {
+    fn entry_key(self) -> Result<PlainStringDeserializer<'static>> {
+        *self.fields = Some(false);
+        if qualified_name_from(self.tag_name) != self.element {
+            return Err(error::with_message(format!(
+                "expected map entry {}, found {}", self.element, qualified_name_from(self.tag_name))));
+        }
+
+        let key = self.key;
+        let index = self.attributes.iter()
+            .position(|attribute| qualified_name_from(&attribute.name) == key)
+            .ok_or_else(|| error::with_message(format!("missing map key attribute {}", key)))?;
+        let key = self.attributes.remove(index).value;
+
+        trace!("found entry {}", key);
+        Ok(PlainStringDeserializer::new(key, self.format))
+    }
+}
+
+macro_rules! forward_to_entry_key {
+    ($($deserialize:ident)*) => {
+        $(
+            fn $deserialize<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.entry_key()?.$deserialize(visitor)
+            }
+        )*
+    }
+}
+
+impl<'b, 'de> serde::de::Deserializer<'de> for EntryKey<'b> {
+    type Error = Error;
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if *self.fields == Some(false) {
+            return self.entry_key()?.deserialize_identifier(visitor);
+        }
+        *self.fields = Some(true);
+        let name = qualified_name_from(self.tag_name);
+        trace!("found subtag {}", name);
+        visitor.visit_string(name)
+    }
+
+    forward_to_entry_key! {
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_option deserialize_unit deserialize_seq
+        deserialize_map deserialize_ignored_any
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value> {
+        self.entry_key()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value> {
+        self.entry_key()?.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        self.entry_key()?.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self, name: &'static str, len: usize, visitor: V
+    ) -> Result<V::Value> {
+        self.entry_key()?.deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self, name: &'static str, fields: &'static [&'static str], visitor: V
+    ) -> Result<V::Value> {
+        self.entry_key()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self, name: &'static str, variants: &'static [&'static str], visitor: V
+    ) -> Result<V::Value> {
+        self.entry_key()?.deserialize_enum(name, variants, visitor)
+    }
+}
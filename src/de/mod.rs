@@ -1,78 +1,439 @@
-use std::io::Read;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read};
+use std::mem;
+use std::sync::Arc;
 
 use serde::de::{DeserializeOwned, Visitor};
 
+use xml::common::Position;
 use xml::name::OwnedName;
 use xml::attribute::OwnedAttribute;
 use xml::reader::{EventReader, ParserConfig, XmlEvent};
 
+use super::binary::BinaryEncoding;
+use super::entities::EntityResolver;
+use super::lexical::{LexicalForm, ValueFormat};
+use super::limits::{Limit, Limits};
+use super::maps::MapRepresentation;
 use super::error::{self, Error, Result};
+use super::prolog::{Prolog, ProcessingInstruction};
 
+mod decode;
+mod expand;
+mod lenient;
 mod map;
 mod seq;
 mod tuple;
 mod var;
 mod plain;
+pub(crate) mod scan;
+pub(crate) mod split;
+mod stream;
 
-use self::map::MapAccess;
+pub use self::decode::DecodingReader;
+pub use self::lenient::Diagnostic;
+pub use self::stream::{StanzaReader, StreamDeserializer, StreamHeader};
+
+use self::lenient::Lenient;
+use self::map::{EntryAccess, MapAccess};
 use self::seq::SeqAccess;
 use self::tuple::TupleAccess;
 use self::var::{VariantAccess, UnitVariantAccess};
 
+/// Decodes the document according to its byte order mark or XML declaration.
 pub fn from_reader<'de, R: Read, T: serde::de::Deserialize<'de>>(reader: R) -> Result<T> {
-    T::deserialize(&mut Deserializer::new_from_reader(reader)?)
+    T::deserialize(&mut Deserializer::new_decoding(DecodingReader::new(reader))?)
+}
+
+/// Like `from_str`, but skips optional or defaulted fields whose values do
+/// not convert, and elements and attributes that are not fields of a struct,
+/// returning what was skipped as diagnostics. The events read are kept, for
+/// the item of a sequence holding what was skipped, or else the document, to
+/// be deserialized again without it.
+pub fn from_str_lenient<T: DeserializeOwned>(s: &str) -> Result<(T, Vec<Diagnostic>)> {
+    from_reader_lenient(s.as_bytes())
+}
+
+/// Like `from_reader`, see `from_str_lenient`.
+pub fn from_reader_lenient<R: Read, T: DeserializeOwned>(reader: R) -> Result<(T, Vec<Diagnostic>)> {
+    Deserializer::new_decoding(DecodingReader::new(reader))?.deserialize_lenient()
+}
+
+/// Decodes the document as `encoding`, whatever it declares.
+pub fn from_reader_with_encoding<'de, R: Read, T: serde::de::Deserialize<'de>>(
+    reader: R, encoding: &str
+) -> Result<T> {
+    let reader = DecodingReader::with_encoding(reader, encoding)?;
+    T::deserialize(&mut Deserializer::new_decoding(reader)?)
+}
+
+pub fn from_reader_with_prolog<'de, R: Read, T: serde::de::Deserialize<'de>>(
+    reader: R
+) -> Result<(Prolog, T)> {
+    with_prolog(Deserializer::new_decoding(DecodingReader::new(reader))?)
 }
 
 pub fn from_str<T>(s: &str) -> Result<T>
 where
     T: DeserializeOwned,
 {
-    from_reader(s.as_bytes())
+    T::deserialize(&mut Deserializer::new_decoding(DecodingReader::with_encoding(s.as_bytes(), "UTF-8")?)?)
+}
+
+pub fn from_str_with_prolog<T>(s: &str) -> Result<(Prolog, T)>
+where
+    T: DeserializeOwned,
+{
+    with_prolog(Deserializer::new_decoding(DecodingReader::with_encoding(s.as_bytes(), "UTF-8")?)?)
+}
+
+fn with_prolog<'de, R: Read, T: serde::de::Deserialize<'de>>(
+    mut de: Deserializer<R>
+) -> Result<(Prolog, T)> {
+    let prolog = de.prolog()?.clone();
+    let value = T::deserialize(&mut de)?;
+    Ok((prolog, value))
+}
+
+/// Options of the deserializers that readers of several documents make for
+/// each of them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Options {
+    pub format: ValueFormat,
+    pub limits: Limits,
+    pub map_representation: MapRepresentation,
+    pub entities: Vec<(String, String)>,
+    pub entity_resolver: Option<SharedResolver>,
+}
+
+/// Entity resolver of the readers of several documents, given to each.
+#[derive(Clone)]
+pub(crate) struct SharedResolver(pub Arc<dyn EntityResolver + Send + Sync>);
+
+impl EntityResolver for SharedResolver {
+    fn resolve(&self, public_id: Option<&str>, system_id: &str) -> io::Result<String> {
+        self.0.resolve(public_id, system_id)
+    }
+}
+
+impl fmt::Debug for SharedResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedResolver")
+    }
+}
+
+impl Options {
+    /// Deserializes a document cut out of a stream, decoded as `encoding` or
+    /// as it declares.
+    pub fn deserialize<T: DeserializeOwned>(&self, document: &[u8], encoding: Option<&str>) -> Result<T> {
+        let mut reader = match encoding {
+            Some(encoding) => DecodingReader::with_encoding(document, encoding)?,
+            None => DecodingReader::new(document),
+        };
+        reader = reader.with_entities(self.entities.iter().cloned());
+        if let Some(ref resolver) = self.entity_resolver {
+            reader = reader.with_entity_resolver(resolver.clone());
+        }
+        let mut de = Deserializer::new_decoding(reader)?
+            .with_limits(self.limits)
+            .with_map_representation(self.map_representation.clone());
+        de.format = self.format;
+        T::deserialize(&mut de)
+    }
 }
 
 pub struct Deserializer<R: Read> {
-    reader: EventReader<R>,
+    reader: EventReader<DecodingReader<R>>,
     root: bool,
     lookahead: Option<XmlEvent>,
+    raw_lookahead: Option<XmlEvent>,
     tag_name: Option<OwnedName>,
     attributes: Option<Vec<OwnedAttribute>>,
+    format: ValueFormat,
+    started: bool,
+    in_prolog: bool,
+    prolog: Prolog,
+    capture_comments: bool,
+    /// Captured comments not taken yet, with the depth of the element they
+    /// are in.
+    comments: VecDeque<(usize, String)>,
+    map_representation: MapRepresentation,
+    limits: Limits,
+    /// Depth of the elements read, and of those taken.
+    depth: usize,
+    open: usize,
+    elements: usize,
+    lenient: Option<Lenient>,
 }
 
 impl<R: Read> Deserializer<R> {
-    pub fn new(reader: EventReader<R>) -> Result<Self> {
+    /// Reads with a parser configured by the caller. The input goes through
+    /// a `DecodingReader`, which enforces the limits on it.
+    pub fn new(mut reader: EventReader<DecodingReader<R>>) -> Result<Self> {
+        let limits = Limits::default();
+        reader.source_mut().set_limits(&limits);
         let d = Deserializer {
             reader,
             root: true,
             lookahead: None,
+            raw_lookahead: None,
             tag_name: None,
             attributes: None,
+            format: ValueFormat::default(),
+            started: false,
+            in_prolog: true,
+            prolog: Prolog::default(),
+            capture_comments: false,
+            comments: VecDeque::new(),
+            map_representation: MapRepresentation::default(),
+            limits,
+            depth: 0,
+            open: 0,
+            elements: 0,
+            lenient: None,
         };
         Ok(d)
     }
 
     pub fn new_from_reader(reader: R) -> Result<Self> {
+        Self::new_decoding(DecodingReader::new(reader))
+    }
+
+    /// Reads from a `DecodingReader`, to force an encoding or to add
+    /// entities.
+    pub fn new_decoding(reader: DecodingReader<R>) -> Result<Self> {
         let config = ParserConfig::new()
             .trim_whitespace(true)
             .whitespace_to_characters(true)
             .cdata_to_characters(true)
-            .ignore_comments(true)
+            .ignore_comments(false)
             .coalesce_characters(true);
 
         Self::new(EventReader::new_with_config(reader, config))
     }
 
+    /// The declaration, processing instructions and document type
+    /// declaration before the root element.
+    pub fn prolog(&mut self) -> Result<&Prolog> {
+        if self.in_prolog {
+            self.start_document()?;
+            self.peek()?;
+        }
+        self.prolog.doctype = self.reader.source().doctype().map(|s| s.to_string());
+        Ok(&self.prolog)
+    }
+
+    /// Sets whether comments inside the root element are passed to the
+    /// structs of the elements they are in as `#comment` fields, trimmed of
+    /// surrounding whitespace and joined by newlines, or one per item of a
+    /// sequence. They are skipped by default.
+    pub fn with_comments(mut self, capture_comments: bool) -> Self {
+        self.capture_comments = capture_comments;
+        self
+    }
+
+    /// Sets how maps are expected to be written, one element named after each
+    /// key by default.
+    pub fn with_map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.map_representation = map_representation;
+        self
+    }
+
+    /// Sets the bounds on what the document may contain, `Limits::default()`
+    /// unless changed.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.reader.source_mut().set_limits(&limits);
+        self
+    }
+
+    /// Deserializes the document as `from_str_lenient` does, with the
+    /// options set on this deserializer.
+    pub fn deserialize_lenient<T: DeserializeOwned>(self) -> Result<(T, Vec<Diagnostic>)> {
+        lenient::deserialize(self)
+    }
+
+    /// Sets how byte strings are expected to be encoded, base64 by default.
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Sets which lexical forms are accepted for booleans and numbers, XML
+    /// Schema ones by default.
+    pub fn with_lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.format.lexical_form = lexical_form;
+        self
+    }
+
     fn peek(&mut self) -> Result<&XmlEvent> {
         trace!("Peeking ...");
         match self.lookahead {
-            None => { self.lookahead = Some(self.do_next()?); Ok(&self.lookahead.as_ref().unwrap()) },
-            Some(ref e) => Ok(&e)
+            None => { self.lookahead = Some(self.do_next()?); Ok(self.lookahead.as_ref().unwrap()) },
+            Some(ref e) => Ok(e)
         }
     }
 
-    fn do_next(&mut self) -> Result<XmlEvent> {
+    fn read_event(&mut self) -> Result<XmlEvent> {
         trace!("Reading from {:p}", &self.reader);
-        match self.reader.next().map_err(error::reader)? {
-            XmlEvent::ProcessingInstruction { .. } => self.do_next(),
+        if let Some(e) = self.raw_lookahead.take() {
+            return Ok(e);
+        }
+        loop {
+            let mut event = self.read_raw()?;
+            let skip = match self.lenient {
+                Some(ref mut lenient) => lenient.filter(&mut event, self.elements),
+                None => false,
+            };
+            if !skip {
+                return Ok(event);
+            }
+            let depth = self.depth - 1;
+            while self.depth > depth {
+                self.read_raw()?;
+            }
+        }
+    }
+
+    fn read_raw(&mut self) -> Result<XmlEvent> {
+        if let Some(event) = self.lenient.as_mut().and_then(Lenient::replay) {
+            self.check_limits(&event)?;
+            return Ok(event);
+        }
+        let event = match self.reader.next() {
+            Ok(event) => event,
+            Err(err) => return Err(match self.reader.source().limit_exceeded() {
+                Some(limit) => error::limit_exceeded(limit, self.limits.max(limit)),
+                None => error::reader(err),
+            }),
+        };
+        if let Some(ref mut lenient) = self.lenient {
+            lenient.record(&event, self.reader.position());
+        }
+        self.check_limits(&event)?;
+        Ok(event)
+    }
+
+    /// Starts over from the first event, for a lenient read.
+    fn restart(&mut self) {
+        if let Some(ref mut lenient) = self.lenient {
+            lenient.rewind();
+        }
+        self.root = true;
+        self.lookahead = None;
+        self.raw_lookahead = None;
+        self.tag_name = None;
+        self.attributes = None;
+        self.started = false;
+        self.in_prolog = true;
+        self.prolog = Prolog::default();
+        self.comments.clear();
+        self.depth = 0;
+        self.open = 0;
+        self.elements = 0;
+    }
+
+    /// Reads up to the end of the element whose start tag was just taken.
+    fn skip_element(&mut self) -> Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                XmlEvent::StartElement { .. } => depth += 1,
+                XmlEvent::EndElement { .. } => depth -= 1,
+                XmlEvent::EndDocument => return Err(error::with_message("unexpected end of document".to_string())),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a value that did not convert as its default when reading
+    /// leniently.
+    fn recover<T: Default>(&mut self, result: Result<T>) -> Result<T> {
+        match (result, self.lenient.as_mut()) {
+            (Ok(value), _) => Ok(value),
+            (Err(err), Some(lenient)) => lenient.substitute(err, None).map(|()| T::default()),
+            (Err(err), None) => Err(err),
+        }
+    }
+
+    fn check_limits(&mut self, event: &XmlEvent) -> Result<()> {
+        let limits = self.limits;
+        match *event {
+            XmlEvent::StartElement { ref attributes, .. } => {
+                self.depth += 1;
+                self.elements += 1;
+                if self.depth > limits.max_depth {
+                    return Err(error::limit_exceeded(Limit::Depth, limits.max_depth));
+                }
+                if self.elements > limits.max_elements {
+                    return Err(error::limit_exceeded(Limit::Elements, limits.max_elements));
+                }
+                if attributes.len() > limits.max_attributes {
+                    return Err(error::limit_exceeded(Limit::Attributes, limits.max_attributes));
+                }
+                for attribute in attributes {
+                    self.check_text_length(&attribute.value)?;
+                }
+            },
+            XmlEvent::EndElement { .. } => self.depth = self.depth.saturating_sub(1),
+            XmlEvent::Characters(ref s) | XmlEvent::Whitespace(ref s) | XmlEvent::CData(ref s)
+                | XmlEvent::Comment(ref s) => self.check_text_length(s)?,
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn check_text_length(&self, text: &str) -> Result<()> {
+        if text.len() > self.limits.max_text_length {
+            Err(error::limit_exceeded(Limit::TextLength, self.limits.max_text_length))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn comment(&mut self, comment: String) {
+        if self.capture_comments && !self.in_prolog {
+            self.comments.push_back((self.depth, comment.trim().to_string()));
+        }
+    }
+
+    fn do_next(&mut self) -> Result<XmlEvent> {
+        match self.read_event()? {
+            XmlEvent::ProcessingInstruction { name, data } => {
+                if self.in_prolog {
+                    self.prolog.processing_instructions.push(ProcessingInstruction { target: name, data });
+                }
+                self.do_next()
+            },
+            XmlEvent::Comment(comment) => {
+                self.comment(comment);
+                self.do_next()
+            },
+            XmlEvent::Characters(mut s) => {
+                // Join the text on both sides of comments
+                loop {
+                    match self.read_event()? {
+                        XmlEvent::Comment(comment) => self.comment(comment),
+                        XmlEvent::Characters(more) => {
+                            s.push_str(&more);
+                            self.check_text_length(&s)?;
+                        },
+                        e => {
+                            self.raw_lookahead = Some(e);
+                            break;
+                        },
+                    }
+                }
+                trace!("event Characters({:?})", s);
+                Ok(XmlEvent::Characters(s))
+            },
+            e @ XmlEvent::StartElement { .. } => {
+                self.in_prolog = false;
+                trace!("event {:?}", e);
+                Ok(e)
+            },
             e => {
                 trace!("event {:?}", e);
                 Ok(e)
@@ -80,12 +441,36 @@ impl<R: Read> Deserializer<R> {
         }
     }
 
+    /// Takes the comments captured in the element at `depth`, read up to its
+    /// end tag, dropping those of the elements it contains.
+    fn take_comments(&mut self, depth: usize) -> Result<Vec<String>> {
+        self.peek()?;
+        let mut taken = Vec::new();
+        for (in_depth, comment) in mem::take(&mut self.comments) {
+            if in_depth == depth {
+                taken.push(comment);
+            } else if in_depth < depth {
+                self.comments.push_back((in_depth, comment));
+            }
+        }
+        Ok(taken)
+    }
+
     fn next(&mut self) -> Result<XmlEvent> {
         trace!("Popping!");
-        match self.lookahead.take() {
-            Some(e) => Ok(e),
-            None => self.do_next(),
+        let event = match self.lookahead.take() {
+            Some(e) => e,
+            None => self.do_next()?,
+        };
+        if let Some(ref mut lenient) = self.lenient {
+            lenient.take(&event);
+        }
+        match event {
+            XmlEvent::StartElement { .. } => self.open += 1,
+            XmlEvent::EndElement { .. } => self.open = self.open.saturating_sub(1),
+            _ => {},
         }
+        Ok(event)
     }
 
     fn current_tag(&self) -> Option<OwnedName> {
@@ -108,15 +493,25 @@ impl<R: Read> Deserializer<R> {
     }
 
     fn start_document(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
         match self.next()? {
-            XmlEvent::StartDocument { .. } => Ok(()),
+            XmlEvent::StartDocument { version, encoding, standalone } => {
+                self.prolog.version = version.into();
+                self.prolog.encoding = self.reader.source().declared_encoding()
+                    .map_or(encoding, |label| label.to_string());
+                self.prolog.standalone = standalone;
+                Ok(())
+            },
             e => Err(error::with_message(format!("expected start document, but got {:?}", e))),
         }
     }
 
     fn end_document(&mut self) -> Result<()> {
         match self.next()? {
-            XmlEvent::EndDocument { .. } => Ok(()),
+            XmlEvent::EndDocument => Ok(()),
             e => Err(error::with_message(format!("expected end of document, but got {:?}", e))),
         }
     }
@@ -144,16 +539,49 @@ impl<R: Read> Deserializer<R> {
     }
 }
 
+impl<R: Read> Deserializer<R> {
+    fn deserialize_fields<'de, V: Visitor<'de>>(
+        &mut self, visitor: V, representation: MapRepresentation, fields: Option<&'static [&'static str]>
+    ) -> Result<V::Value> {
+        if self.root {
+            self.root = false;
+            self.start_document()?;
+            let (tag_name, attributes) = self.start_tag()?;
+            self.put_attributes(attributes);
+
+            let v = self.visit_fields(visitor, representation, fields)?;
+
+            let _ = self.end_tag(&tag_name);
+            self.end_document()?;
+            Ok(v)
+        } else {
+            self.visit_fields(visitor, representation, fields)
+        }
+    }
+
+    fn visit_fields<'de, V: Visitor<'de>>(
+        &mut self, visitor: V, representation: MapRepresentation, fields: Option<&'static [&'static str]>
+    ) -> Result<V::Value> {
+        let attributes = self.take_attributes();
+        match representation {
+            MapRepresentation::Elements => visitor.visit_map(MapAccess::new(self, attributes, fields)),
+            MapRepresentation::Entries { element, key } => {
+                visitor.visit_map(EntryAccess::new(self, attributes, element, key))
+            },
+        }
+    }
+}
+
 macro_rules! deserialize_type {
-    ($deserialize:ident, $error:expr => $visit:ident) => {
+    ($deserialize:ident, $parse:ident => $visit:ident) => {
         fn $deserialize<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-            let value = self.characters()?.parse().map_err($error)?;
-            visitor.$visit(value)
+            let value = self.format.$parse(&self.characters()?);
+            visitor.$visit(self.recover(value)?)
         }
     }
 }
 
-impl<'de, 'r, R: Read> serde::de::Deserializer<'de> for &'r mut Deserializer<R> {
+impl<'de, R: Read> serde::de::Deserializer<'de> for &mut Deserializer<R> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
@@ -175,9 +603,9 @@ impl<'de, 'r, R: Read> serde::de::Deserializer<'de> for &'r mut Deserializer<R>
             Ok(v)
         } else {
             match *self.peek()? {
-                XmlEvent::StartElement { .. } => self.deserialize_map(visitor),
+                XmlEvent::StartElement { .. } => self.deserialize_fields(visitor, MapRepresentation::Elements, None),
                 _ => if self.attributes.is_some() {
-                    self.deserialize_map(visitor)
+                    self.deserialize_fields(visitor, MapRepresentation::Elements, None)
                 } else {
                     self.deserialize_string(visitor)
                 },
@@ -185,27 +613,23 @@ impl<'de, 'r, R: Read> serde::de::Deserializer<'de> for &'r mut Deserializer<R>
         }
     }
 
-    deserialize_type!(deserialize_bool, error::parse_bool => visit_bool);
-    deserialize_type!(deserialize_i8, error::parse_int => visit_i8);
-    deserialize_type!(deserialize_i16, error::parse_int => visit_i16);
-    deserialize_type!(deserialize_i32, error::parse_int => visit_i32);
-    deserialize_type!(deserialize_i64, error::parse_int => visit_i64);
+    deserialize_type!(deserialize_bool, parse_bool => visit_bool);
+    deserialize_type!(deserialize_i8, parse_int => visit_i8);
+    deserialize_type!(deserialize_i16, parse_int => visit_i16);
+    deserialize_type!(deserialize_i32, parse_int => visit_i32);
+    deserialize_type!(deserialize_i64, parse_int => visit_i64);
 
-    serde_if_integer128! {
-        deserialize_type!(deserialize_i128, error::parse_int => visit_i128);
-    }
+    deserialize_type!(deserialize_i128, parse_int => visit_i128);
 
-    deserialize_type!(deserialize_u8, error::parse_int => visit_u8);
-    deserialize_type!(deserialize_u16, error::parse_int => visit_u16);
-    deserialize_type!(deserialize_u32, error::parse_int => visit_u32);
-    deserialize_type!(deserialize_u64, error::parse_int => visit_u64);
+    deserialize_type!(deserialize_u8, parse_int => visit_u8);
+    deserialize_type!(deserialize_u16, parse_int => visit_u16);
+    deserialize_type!(deserialize_u32, parse_int => visit_u32);
+    deserialize_type!(deserialize_u64, parse_int => visit_u64);
 
-    serde_if_integer128! {
-        deserialize_type!(deserialize_u128, error::parse_int => visit_u128);
-    }
+    deserialize_type!(deserialize_u128, parse_int => visit_u128);
 
-    deserialize_type!(deserialize_f32, error::parse_float => visit_f32);
-    deserialize_type!(deserialize_f64, error::parse_float => visit_f64);
+    deserialize_type!(deserialize_f32, parse_float => visit_f32);
+    deserialize_type!(deserialize_f64, parse_float => visit_f64);
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
@@ -233,7 +657,11 @@ impl<'de, 'r, R: Read> serde::de::Deserializer<'de> for &'r mut Deserializer<R>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        let s = match *self.peek()? {
+            XmlEvent::EndElement { .. } => String::new(),
+            _ => self.characters()?,
+        };
+        visitor.visit_byte_buf(self.format.binary_encoding.decode(&s)?)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
@@ -301,38 +729,26 @@ impl<'de, 'r, R: Read> serde::de::Deserializer<'de> for &'r mut Deserializer<R>
         visitor.visit_seq(TupleAccess::new(self)?)
     }
 
-    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         trace!("Map");
-        if self.root {
-            self.root = false;
-            self.start_document()?;
-            let (tag_name, attributes) = self.start_tag()?;
-
-            let v = visitor.visit_map(MapAccess::new(&mut self, attributes))?;
-
-            let _ = self.end_tag(&tag_name);
-            self.end_document()?;
-            Ok(v)
-        } else {
-            let attributes = self.take_attributes();
-            visitor.visit_map(MapAccess::new(&mut self, attributes))
-        }
+        let representation = self.map_representation.clone();
+        self.deserialize_fields(visitor, representation, None)
     }
 
     fn deserialize_struct<V>(
         self,
         name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         trace!("Struct {}", name);
-        self.deserialize_map(visitor)
+        self.deserialize_fields(visitor, MapRepresentation::Elements, Some(fields))
     }
 
     fn deserialize_enum<V>(
@@ -344,6 +760,21 @@ impl<'de, 'r, R: Read> serde::de::Deserializer<'de> for &'r mut Deserializer<R>
     where
         V: Visitor<'de>,
     {
+        // The root element names the variant
+        if self.root {
+            self.root = false;
+            self.start_document()?;
+            let (tag_name, attributes) = self.start_tag()?;
+            self.put_attributes(attributes);
+            self.tag_name = Some(tag_name.clone());
+
+            let v = visitor.visit_enum(VariantAccess::new(self)?)?;
+
+            let _ = self.end_tag(&tag_name);
+            self.end_document()?;
+            return Ok(v);
+        }
+
         match self.peek()? {
             XmlEvent::StartElement { .. } => {
                 let (tag_name, _) = self.start_tag()?;
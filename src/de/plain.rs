@@ -1,25 +1,52 @@
-use serde::de::Visitor;
+use serde::de::{IntoDeserializer, Visitor};
 
+use super::lenient::Lenient;
+use super::tuple::TupleAccess;
+use super::super::lexical::ValueFormat;
 use super::super::error::{self, Error, Result};
 
-pub struct PlainStringDeserializer(pub String);
+pub struct PlainStringDeserializer<'a> {
+    value: String,
+    format: ValueFormat,
+    /// A lenient read, and the attribute whose value this is.
+    lenient: Option<(&'a mut Lenient, Option<String>)>,
+}
+
+impl<'a> PlainStringDeserializer<'a> {
+    pub fn new(value: String, format: ValueFormat) -> Self {
+        PlainStringDeserializer { value, format, lenient: None }
+    }
+
+    /// Reads values that do not convert as their default, see
+    /// `Deserializer::recover`.
+    pub fn with_lenient(mut self, lenient: &'a mut Lenient, attribute: Option<String>) -> Self {
+        self.lenient = Some((lenient, attribute));
+        self
+    }
 
-impl PlainStringDeserializer {
     fn characters(self) -> Result<String> {
-        Ok(self.0)
+        Ok(self.value)
+    }
+
+    fn recover<T: Default>(self, result: Result<T>) -> Result<T> {
+        match (result, self.lenient) {
+            (Ok(value), _) => Ok(value),
+            (Err(err), Some((lenient, attribute))) => lenient.substitute(err, attribute).map(|()| T::default()),
+            (Err(err), None) => Err(err),
+        }
     }
 }
 
 macro_rules! deserialize_attr_type {
-    ($deserialize:ident, $error:expr => $visit:ident) => {
+    ($deserialize:ident, $parse:ident => $visit:ident) => {
         fn $deserialize<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-            let value = self.characters()?.parse().map_err($error)?;
-            visitor.$visit(value)
+            let value = self.format.$parse(&self.value);
+            visitor.$visit(self.recover(value)?)
         }
     }
 }
 
-impl<'de> serde::de::Deserializer<'de> for PlainStringDeserializer {
+impl<'a, 'de> serde::de::Deserializer<'de> for PlainStringDeserializer<'a> {
     type Error = Error;
     
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
@@ -29,49 +56,124 @@ impl<'de> serde::de::Deserializer<'de> for PlainStringDeserializer {
         visitor.visit_string(self.characters()?)
     }
 
-    deserialize_attr_type!(deserialize_bool, error::parse_bool => visit_bool);
-    deserialize_attr_type!(deserialize_i8, error::parse_int => visit_i8);
-    deserialize_attr_type!(deserialize_i16, error::parse_int => visit_i16);
-    deserialize_attr_type!(deserialize_i32, error::parse_int => visit_i32);
-    deserialize_attr_type!(deserialize_i64, error::parse_int => visit_i64);
+    deserialize_attr_type!(deserialize_bool, parse_bool => visit_bool);
+    deserialize_attr_type!(deserialize_i8, parse_int => visit_i8);
+    deserialize_attr_type!(deserialize_i16, parse_int => visit_i16);
+    deserialize_attr_type!(deserialize_i32, parse_int => visit_i32);
+    deserialize_attr_type!(deserialize_i64, parse_int => visit_i64);
 
-    serde_if_integer128! {
-        deserialize_attr_type!(deserialize_i128, error::parse_int => visit_i128);
+    deserialize_attr_type!(deserialize_i128, parse_int => visit_i128);
+
+    deserialize_attr_type!(deserialize_u8, parse_int => visit_u8);
+    deserialize_attr_type!(deserialize_u16, parse_int => visit_u16);
+    deserialize_attr_type!(deserialize_u32, parse_int => visit_u32);
+    deserialize_attr_type!(deserialize_u64, parse_int => visit_u64);
+    deserialize_attr_type!(deserialize_u128, parse_int => visit_u128);
+
+    deserialize_attr_type!(deserialize_f32, parse_float => visit_f32);
+    deserialize_attr_type!(deserialize_f64, parse_float => visit_f64);
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_char(single_char(&self.characters()?)?)
     }
 
-    deserialize_attr_type!(deserialize_u8, error::parse_int => visit_u8);
-    deserialize_attr_type!(deserialize_u16, error::parse_int => visit_u16);
-    deserialize_attr_type!(deserialize_u32, error::parse_int => visit_u32);
-    deserialize_attr_type!(deserialize_u64, error::parse_int => visit_u64);
-    
-    serde_if_integer128! {
-        deserialize_attr_type!(deserialize_u128, error::parse_int => visit_u128);
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
     }
 
-    deserialize_attr_type!(deserialize_f32, error::parse_float => visit_f32);
-    deserialize_attr_type!(deserialize_f64, error::parse_float => visit_f64);
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
 
-    fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        visitor.visit_some(self)
     }
     
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let format = self.format;
+        visitor.visit_byte_buf(format.binary_encoding.decode(&self.characters()?)?)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let format = self.format;
+        visitor.visit_seq(TupleAccess::from_characters(&self.characters()?, format))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(error::with_message(
+            "a sequence cannot be read from text, use serde_xml::List for whitespace-separated items".into()))
+    }
+
     fn deserialize_enum<V>(
         self,
         _name: &str,
         _variants: &'static [&'static str],
-        _visitor: V,
+        visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        let variant: serde::de::value::StringDeserializer<Error> = self.characters()?.into_deserializer();
+        visitor.visit_enum(variant)
     }
 
     forward_to_deserialize_any! {
-        char str string unit seq bytes map unit_struct newtype_struct tuple_struct
-        struct identifier tuple ignored_any byte_buf
+        str string map
+        struct identifier ignored_any
+    }
+}
+
+pub fn single_char(s: &str) -> Result<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(error::with_message(format!("expected a single character but got {:?}", s))),
     }
 }
@@ -0,0 +1,83 @@
+//! Finds where markup ends in input that has not been parsed yet, skipping
+//! quoted literals. Only ASCII markup characters are looked for, so that the
+//! positions found in the bytes of a `str` are character boundaries.
+
+/// Kind of markup found by `scan_markup`.
+pub(crate) enum Markup {
+    StartTag { empty: bool },
+    EndTag,
+    /// Comment, CDATA section, processing instruction or declaration.
+    Other,
+}
+
+/// Length of the markup `input` starts with, or `None` if it is incomplete.
+pub(crate) fn scan_markup(input: &[u8]) -> Option<(Markup, usize)> {
+    if input.starts_with(b"<!--") {
+        find(input, b"-->").map(|end| (Markup::Other, end + 3))
+    } else if input.starts_with(b"<![CDATA[") {
+        find(input, b"]]>").map(|end| (Markup::Other, end + 3))
+    } else if input.starts_with(b"<?") {
+        find(input, b"?>").map(|end| (Markup::Other, end + 2))
+    } else if input.len() < 9 && (b"<![CDATA[".starts_with(input) || b"<!--".starts_with(input)) {
+        None
+    } else if input.starts_with(b"<!") {
+        declaration_end(input).map(|end| (Markup::Other, end))
+    } else if input.starts_with(b"</") {
+        tag_end(input).map(|end| (Markup::EndTag, end))
+    } else {
+        let end = tag_end(input)?;
+        Some((Markup::StartTag { empty: input[end - 2] == b'/' }, end))
+    }
+}
+
+/// End of a tag, past its `>`.
+pub(crate) fn tag_end(tag: &[u8]) -> Option<usize> {
+    unquoted(tag, b'>', false).map(|end| end + 1)
+}
+
+/// End of a declaration, past its `>`, skipping the internal subset of a
+/// document type declaration.
+pub(crate) fn declaration_end(declaration: &[u8]) -> Option<usize> {
+    unquoted(declaration, b'>', true).map(|end| end + 1)
+}
+
+/// Position of the `[` opening the internal subset of a document type
+/// declaration, if any.
+pub(crate) fn subset_start(doctype: &[u8]) -> Option<usize> {
+    unquoted(doctype, b'[', false)
+}
+
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Position of the first `stop` outside quoted literals, and outside square
+/// brackets if `brackets` is set. Within brackets, comments and processing
+/// instructions are skipped, as their text is not quoted.
+fn unquoted(input: &[u8], stop: u8, brackets: bool) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0;
+    let mut i = 0;
+    while i < input.len() {
+        let b = input[i];
+        match (quote, b) {
+            (Some(q), _) if b == q => quote = None,
+            (Some(_), _) => {},
+            (None, b'<') if depth > 0 && input[i..].starts_with(b"<!--") => {
+                i += find(&input[i..], b"-->")? + 3;
+                continue;
+            },
+            (None, b'<') if depth > 0 && input[i..].starts_with(b"<?") => {
+                i += find(&input[i..], b"?>")? + 2;
+                continue;
+            },
+            (None, b'"') | (None, b'\'') => quote = Some(b),
+            (None, _) if b == stop && depth == 0 => return Some(i),
+            (None, b'[') if brackets => depth += 1,
+            (None, b']') if brackets => depth -= 1,
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
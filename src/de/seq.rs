@@ -3,7 +3,7 @@ use std::io::Read;
 use xml::name::OwnedName;
 use xml::reader::XmlEvent;
 
-use super::Deserializer;
+use super::{lenient, Deserializer};
 use super::super::error::{self, Error, Result};
 
 pub struct SeqAccess<'a, R: 'a + Read> {
@@ -18,6 +18,23 @@ impl<'a, R: 'a + Read> SeqAccess<'a, R> {
             .ok_or(error::with_message("expected current tag".to_string()))?;
         Ok(SeqAccess { de, tag_name, first: true })
     }
+
+    /// Takes the start tag of the next item, returning whether there is one.
+    fn next_item(&mut self) -> Result<bool> {
+        if self.first {
+            self.first = false;
+            return Ok(true);
+        }
+        self.de.end_tag(&self.tag_name)?;
+        match self.de.peek()?.clone() {
+            XmlEvent::StartElement { ref name, .. } if name == &self.tag_name => {
+                let (_, attributes) = self.de.start_tag()?;
+                self.de.put_attributes(attributes);
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
 }
 
 impl<'de, 'a, R: 'a + Read> serde::de::SeqAccess<'de> for SeqAccess<'a, R> {
@@ -28,19 +45,18 @@ impl<'de, 'a, R: 'a + Read> serde::de::SeqAccess<'de> for SeqAccess<'a, R> {
         seed: T,
     ) -> Result<Option<T::Value>>
     {
-        if self.first {
-            self.first = false;
+        if self.next_item()? {
             seed.deserialize(&mut *self.de).map(Some)
         } else {
-            self.de.end_tag(&self.tag_name)?;
-            match self.de.peek()?.clone() {
-                XmlEvent::StartElement { ref name, .. } if name == &self.tag_name => {
-                    self.de.start_tag()?;
-                    let v = seed.deserialize(&mut *self.de)?;
-                    Ok(Some(v))
-                },
-                _ => Ok(None),
-            }
+            Ok(None)
+        }
+    }
+
+    fn next_element<T: serde::de::Deserialize<'de>>(&mut self) -> Result<Option<T>> {
+        if self.next_item()? {
+            lenient::item(self.de).map(Some)
+        } else {
+            Ok(None)
         }
     }
 }
@@ -0,0 +1,311 @@
+use limits::{Limit, Limits};
+
+use super::decode::declared_encoding;
+use super::error::{self, Result};
+use super::scan::{scan_markup, tag_end, Markup};
+
+/// Next piece of the input cut by `RecordSplitter`.
+pub enum Split {
+    /// A complete record: a child of the root element, with the namespace
+    /// declarations of the root element copied onto it, or a whole document.
+    Record(Vec<u8>),
+    /// The start tag of the root element has been read.
+    Root,
+    /// The buffered input ends in the middle of some markup.
+    NeedMore,
+    /// The root element is closed.
+    End,
+}
+
+/// Cuts a document into the children of its root element, or a stream into
+/// the documents it is made of, as its bytes come in, so that each can be
+/// deserialized on its own.
+///
+/// Only encodings that write markup characters as ASCII are supported.
+pub struct RecordSplitter {
+    buffer: Vec<u8>,
+    /// Most bytes to buffer for a record that is not complete yet.
+    limit: usize,
+    position: usize,
+    /// Bytes dropped from the buffer so far.
+    drained: usize,
+    /// Depth of the elements that are records: 1 for the children of the
+    /// root element, 0 for whole documents.
+    level: usize,
+    encoding: Option<String>,
+    root: Option<Vec<u8>>,
+    root_namespaces: Vec<(Vec<u8>, Vec<u8>)>,
+    depth: usize,
+    closed: bool,
+    record_start: usize,
+    /// Start of the markup preceding the root element of the next document.
+    prolog_start: Option<usize>,
+    /// Past the root element of a document, whose trailing comments and
+    /// processing instructions are dropped until the next one starts.
+    trailing: bool,
+    started: bool,
+}
+
+impl RecordSplitter {
+    /// Records are the children of the root element.
+    pub fn new() -> Self {
+        RecordSplitter {
+            buffer: Vec::new(),
+            limit: Limits::default().max_input_bytes,
+            position: 0,
+            drained: 0,
+            level: 1,
+            encoding: None,
+            root: None,
+            root_namespaces: Vec::new(),
+            depth: 0,
+            closed: false,
+            record_start: 0,
+            prolog_start: None,
+            trailing: false,
+            started: false,
+        }
+    }
+
+    /// Records are documents following one another, each with its own
+    /// prolog.
+    pub fn documents() -> Self {
+        RecordSplitter { level: 0, ..Self::new() }
+    }
+
+    /// Fails once more than `limit` bytes are buffered for a single record.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Encoding declared by the document, once its root element is found.
+    pub fn encoding(&self) -> &str {
+        self.encoding.as_deref().unwrap_or("UTF-8")
+    }
+
+    /// Start tag of the root element, once it has been read.
+    pub fn root(&self) -> Option<&[u8]> {
+        self.root.as_deref()
+    }
+
+    /// Offset in the input of the end of what has been split so far.
+    pub fn offset(&self) -> usize {
+        self.drained + self.position
+    }
+
+    /// Whether the input read so far ends in the middle of a record.
+    pub fn is_incomplete(&self) -> bool {
+        self.depth > self.level || self.buffer[self.position..].iter().any(|b| !b.is_ascii_whitespace())
+    }
+
+    pub fn next(&mut self) -> Result<Split> {
+        if self.closed {
+            return Ok(Split::End);
+        }
+        if !self.started {
+            if self.buffer.len() < 3 {
+                return Ok(Split::NeedMore);
+            }
+            match &self.buffer[..2] {
+                b"\xFF\xFE" | b"\xFE\xFF" => {
+                    return Err(error::with_message("UTF-16 documents cannot be split into records".into()));
+                },
+                _ => {},
+            }
+            if self.buffer.starts_with(b"\xEF\xBB\xBF") {
+                self.position = 3;
+            }
+            self.started = true;
+        }
+
+        loop {
+            let text_end = self.buffer[self.position..].iter().position(|&b| b == b'<')
+                .map_or(self.buffer.len(), |offset| self.position + offset);
+            if self.level == 0 && self.depth == 0 {
+                let text = &self.buffer[self.position..text_end];
+                if let Some(offset) = text.iter().position(|b| !b.is_ascii_whitespace()) {
+                    return Err(error::with_message(
+                        format!("text outside of a document at byte {}", self.offset() + offset)));
+                }
+            }
+
+            let start = text_end;
+            if start == self.buffer.len() {
+                self.position = start;
+                if self.outside_records() {
+                    // Text outside of the records is of no use
+                    self.compact();
+                }
+                return self.need_more();
+            }
+            let (markup, len) = match scan_markup(&self.buffer[start..]) {
+                Some(markup) => markup,
+                None => {
+                    self.position = start;
+                    if self.outside_records() {
+                        self.compact();
+                    }
+                    return self.need_more();
+                },
+            };
+            let end = start + len;
+            self.position = end;
+
+            match (self.depth, markup) {
+                (0, Markup::StartTag { empty }) if self.level == 0 => {
+                    self.record_start = self.prolog_start.take().unwrap_or(start);
+                    self.trailing = empty;
+                    if empty {
+                        return Ok(Split::Record(self.take_record()));
+                    }
+                    self.depth = 1;
+                },
+                (0, Markup::Other) if self.level == 0 => {
+                    // Comments and processing instructions following a
+                    // document are dropped, up to the XML or document type
+                    // declaration of the next one
+                    let markup = &self.buffer[start..end];
+                    if !self.trailing || is_declaration(markup) || markup.starts_with(b"<!DOCTYPE") {
+                        self.trailing = false;
+                        self.prolog_start.get_or_insert(start);
+                    }
+                },
+                (0, Markup::StartTag { empty }) => {
+                    let root = self.buffer[start..end].to_vec();
+                    self.root_namespaces = attributes(&root).into_iter()
+                        .filter(|(name, _)| name == b"xmlns" || name.starts_with(b"xmlns:"))
+                        .collect();
+                    self.root = Some(root);
+                    if empty {
+                        self.closed = true;
+                    } else {
+                        self.depth = 1;
+                    }
+                    return Ok(Split::Root);
+                },
+                (0, Markup::Other) if is_declaration(&self.buffer[start..end]) => {
+                    self.encoding = declared_encoding(&self.buffer[start..end])
+                        .map(|label| String::from_utf8_lossy(label).into_owned());
+                },
+                (0, Markup::EndTag) => {
+                    return Err(error::with_message(format!("unexpected end tag at byte {}", self.drained + start)));
+                },
+                (1, Markup::StartTag { empty }) if self.level == 1 => {
+                    self.record_start = start;
+                    if empty {
+                        return Ok(Split::Record(self.take_record()));
+                    }
+                    self.depth = 2;
+                },
+                (1, Markup::EndTag) if self.level == 1 => {
+                    self.depth = 0;
+                    self.closed = true;
+                    return Ok(Split::End);
+                },
+                (_, Markup::StartTag { empty: false }) if self.depth > self.level => self.depth += 1,
+                (_, Markup::EndTag) if self.depth > self.level => {
+                    self.depth -= 1;
+                    if self.depth == self.level {
+                        self.trailing = self.level == 0;
+                        return Ok(Split::Record(self.take_record()));
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Asks for more input, unless what is buffered is already too much.
+    fn need_more(&self) -> Result<Split> {
+        if self.buffer.len() > self.limit {
+            Err(error::limit_exceeded(Limit::InputBytes, self.limit))
+        } else {
+            Ok(Split::NeedMore)
+        }
+    }
+
+    /// Whether what comes before the current position can be dropped.
+    fn outside_records(&self) -> bool {
+        match self.level {
+            0 => self.depth == 0 && self.prolog_start.is_none(),
+            _ => self.depth <= 1,
+        }
+    }
+
+    fn take_record(&mut self) -> Vec<u8> {
+        let record = &self.buffer[self.record_start..self.position];
+        if self.root_namespaces.is_empty() {
+            let record = record.to_vec();
+            self.compact();
+            return record;
+        }
+
+        let name_end = record.iter()
+            .position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>')
+            .unwrap_or(record.len());
+        let declared = attributes(&record[..tag_end(record).unwrap_or(record.len())]);
+
+        let mut output = Vec::with_capacity(record.len());
+        output.extend_from_slice(&record[..name_end]);
+        for (name, value) in &self.root_namespaces {
+            if !declared.iter().any(|(declared, _)| declared == name) {
+                output.push(b' ');
+                output.extend_from_slice(name);
+                output.push(b'=');
+                output.extend_from_slice(value);
+            }
+        }
+        output.extend_from_slice(&record[name_end..]);
+
+        self.compact();
+        output
+    }
+
+    /// Drops the input that has been dealt with.
+    fn compact(&mut self) {
+        self.drained += self.position;
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        self.record_start = 0;
+    }
+}
+
+fn is_declaration(markup: &[u8]) -> bool {
+    markup.starts_with(b"<?xml") && markup.get(5).is_some_and(|b| b.is_ascii_whitespace())
+}
+
+/// Attribute names and quoted values of a start tag.
+fn attributes(tag: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut attributes = Vec::new();
+    let mut rest = match tag.iter().position(|b| b.is_ascii_whitespace()) {
+        Some(start) => &tag[start..],
+        None => return attributes,
+    };
+    loop {
+        let start = match rest.iter().position(|&b| !b.is_ascii_whitespace() && b != b'/' && b != b'>') {
+            Some(start) => start,
+            None => return attributes,
+        };
+        rest = &rest[start..];
+        let name_end = match rest.iter().position(|&b| b == b'=' || b.is_ascii_whitespace()) {
+            Some(end) => end,
+            None => return attributes,
+        };
+        let name = rest[..name_end].to_vec();
+        let value_start = match rest.iter().position(|&b| b == b'"' || b == b'\'') {
+            Some(start) => start,
+            None => return attributes,
+        };
+        let quote = rest[value_start];
+        let value_end = match rest[value_start + 1..].iter().position(|&b| b == quote) {
+            Some(end) => value_start + end + 2,
+            None => return attributes,
+        };
+        attributes.push((name, rest[value_start..value_end].to_vec()));
+        rest = &rest[value_end..];
+    }
+}
@@ -0,0 +1,349 @@
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+
+use xml::namespace::{NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+use xml::reader::{EventReader, XmlEvent};
+
+use binary::BinaryEncoding;
+use entities::EntityResolver;
+use lexical::LexicalForm;
+use limits::Limits;
+use maps::MapRepresentation;
+
+use super::decode::DecodingReader;
+use super::error::{self, Result};
+use super::split::{RecordSplitter, Split};
+use super::{Options, SharedResolver};
+
+/// Start tag of the root element of an open-ended stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamHeader {
+    /// Qualified name, such as `stream:stream`.
+    pub name: String,
+    /// Qualified names and values of the attributes, namespace declarations
+    /// left out.
+    pub attributes: Vec<(String, String)>,
+    /// Prefixes and URIs of the namespaces declared on the root element, with
+    /// an empty prefix for the default namespace.
+    pub namespaces: Vec<(String, String)>,
+}
+
+/// Reads a document whose root element stays open for as long as the
+/// connection does, as in XMPP, and deserializes each child of the root as
+/// soon as its end tag has been read.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # extern crate serde_xml;
+/// # use serde_xml::StanzaReader;
+/// #[derive(Debug, PartialEq, Deserialize)]
+/// struct Message {
+///     body: String,
+/// }
+///
+/// # fn main() {
+/// let input = "<stream:stream xmlns='jabber:client' \
+///     xmlns:stream='http://etherx.jabber.org/streams' to='example.com'>\
+///     <message><body>Hi</body></message>";
+/// let mut reader = StanzaReader::new(input.as_bytes());
+///
+/// assert_eq!("stream:stream", reader.header().unwrap().name);
+/// let message: Option<Message> = reader.next_stanza().unwrap();
+/// assert_eq!(Some(Message { body: "Hi".to_string() }), message);
+/// # }
+/// ```
+pub struct StanzaReader<R: Read> {
+    reader: R,
+    splitter: RecordSplitter,
+    header: Option<StreamHeader>,
+    options: Options,
+}
+
+impl<R: Read> StanzaReader<R> {
+    pub fn new(reader: R) -> Self {
+        StanzaReader { reader, splitter: RecordSplitter::new(), header: None, options: Options::default() }
+    }
+
+    /// Sets the bounds on each stanza, `Limits::default()` unless changed.
+    /// `max_input_bytes` also bounds what is buffered until one is complete.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self.splitter.set_limit(limits.max_input_bytes);
+        self
+    }
+
+    /// Sets how byte strings are expected to be encoded, base64 by default.
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.options.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Sets which lexical forms are accepted for booleans and numbers, XML
+    /// Schema ones by default.
+    pub fn with_lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.options.format.lexical_form = lexical_form;
+        self
+    }
+
+    /// Sets how maps are expected to be written, one element named after each
+    /// key by default.
+    pub fn with_map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.options.map_representation = map_representation;
+        self
+    }
+
+    /// Expands `&name;` to the text `value` for each of `entities` in each
+    /// stanza, as `DecodingReader::with_entities` does.
+    pub fn with_entities<I, K, V>(mut self, entities: I) -> Self
+        where I: IntoIterator<Item = (K, V)>, K: Into<String>, V: Into<String>
+    {
+        self.options.entities.extend(entities.into_iter().map(|(name, value)| (name.into(), value.into())));
+        self
+    }
+
+    /// Expands the external entities of each stanza with `resolver`, as
+    /// `DecodingReader::with_entity_resolver` does.
+    pub fn with_entity_resolver<E: EntityResolver + Send + Sync + 'static>(mut self, resolver: E) -> Self {
+        self.options.entity_resolver = Some(SharedResolver(Arc::new(resolver)));
+        self
+    }
+
+    /// Reads up to the start tag of the root element, if not done yet.
+    pub fn header(&mut self) -> Result<&StreamHeader> {
+        while self.header.is_none() {
+            self.advance()?;
+        }
+        Ok(self.header.as_ref().expect("read above"))
+    }
+
+    /// Next child of the root element, or `None` once the root element is
+    /// closed.
+    pub fn next_stanza<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+        loop {
+            match self.advance()? {
+                Split::Record(stanza) => {
+                    return self.options.deserialize(&stanza, Some(self.splitter.encoding())).map(Some);
+                },
+                Split::End => return Ok(None),
+                Split::Root | Split::NeedMore => {},
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Splits off what has been read, reading more if that is not enough.
+    fn advance(&mut self) -> Result<Split> {
+        match self.splitter.next()? {
+            Split::Root => {
+                let root = self.splitter.root().expect("root just read");
+                self.header = Some(parse_header(root, self.splitter.encoding())?);
+                Ok(Split::Root)
+            },
+            Split::NeedMore => {
+                if read_more(&mut self.reader, &mut self.splitter)? {
+                    Ok(Split::NeedMore)
+                } else if self.splitter.is_incomplete() {
+                    Err(error::with_message("unexpected end of stream in the middle of a stanza".into()))
+                } else {
+                    Err(error::with_message("unexpected end of stream".into()))
+                }
+            },
+            split => Ok(split),
+        }
+    }
+}
+
+/// Deserializes the documents of a stream one after the other, such as
+/// documents written back to back, each with its own XML declaration, or
+/// the top-level elements of a fragment.
+///
+/// Each document ends with its root element: the comments and processing
+/// instructions that follow it are dropped rather than read as the prolog of
+/// the next one. Each document is decoded according to its own declaration,
+/// and read with the options given to the stream. A document that fails to
+/// deserialize does not stop the iteration, malformed markup or an I/O error
+/// does.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # extern crate serde_xml;
+/// # use serde_xml::StreamDeserializer;
+/// #[derive(Debug, PartialEq, Deserialize)]
+/// struct Event {
+///     level: String,
+/// }
+///
+/// # fn main() {
+/// let input = "<?xml version='1.0'?><event><level>info</level></event>\n\
+///     <?xml version='1.0'?><event><level>warn</level></event>";
+/// let mut events = StreamDeserializer::<_, Event>::new(input.as_bytes());
+///
+/// assert_eq!(Event { level: "info".to_string() }, events.next().unwrap().unwrap());
+/// assert_eq!(55, events.byte_offset());
+/// assert_eq!(Event { level: "warn".to_string() }, events.next().unwrap().unwrap());
+/// assert!(events.next().is_none());
+/// # }
+/// ```
+pub struct StreamDeserializer<R: Read, T> {
+    reader: R,
+    splitter: RecordSplitter,
+    offset: usize,
+    failed: bool,
+    options: Options,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: DeserializeOwned> StreamDeserializer<R, T> {
+    pub fn new(reader: R) -> Self {
+        StreamDeserializer {
+            reader,
+            splitter: RecordSplitter::documents(),
+            offset: 0,
+            failed: false,
+            options: Options::default(),
+            value: PhantomData,
+        }
+    }
+
+    /// Sets the bounds on each document, `Limits::default()` unless changed.
+    /// `max_input_bytes` also bounds what is buffered until one is complete.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self.splitter.set_limit(limits.max_input_bytes);
+        self
+    }
+
+    /// Sets how byte strings are expected to be encoded, base64 by default.
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.options.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Sets which lexical forms are accepted for booleans and numbers, XML
+    /// Schema ones by default.
+    pub fn with_lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.options.format.lexical_form = lexical_form;
+        self
+    }
+
+    /// Sets how maps are expected to be written, one element named after each
+    /// key by default.
+    pub fn with_map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.options.map_representation = map_representation;
+        self
+    }
+
+    /// Expands `&name;` to the text `value` for each of `entities` in each
+    /// document, as `DecodingReader::with_entities` does.
+    pub fn with_entities<I, K, V>(mut self, entities: I) -> Self
+        where I: IntoIterator<Item = (K, V)>, K: Into<String>, V: Into<String>
+    {
+        self.options.entities.extend(entities.into_iter().map(|(name, value)| (name.into(), value.into())));
+        self
+    }
+
+    /// Expands the external entities of each document with `resolver`, as
+    /// `DecodingReader::with_entity_resolver` does.
+    pub fn with_entity_resolver<E: EntityResolver + Send + Sync + 'static>(mut self, resolver: E) -> Self {
+        self.options.entity_resolver = Some(SharedResolver(Arc::new(resolver)));
+        self
+    }
+
+    /// Offset of the byte following the last document returned, whether it
+    /// deserialized or not.
+    pub fn byte_offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn next_document(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            match self.splitter.next()? {
+                Split::Record(document) => {
+                    self.offset = self.splitter.offset();
+                    return Ok(Some(document));
+                },
+                Split::NeedMore | Split::Root | Split::End => {
+                    if !read_more(&mut self.reader, &mut self.splitter)? {
+                        return if self.splitter.is_incomplete() {
+                            Err(error::with_message(format!(
+                                "unexpected end of stream in the document starting at byte {}", self.offset)))
+                        } else {
+                            Ok(None)
+                        };
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for StreamDeserializer<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.failed {
+            return None;
+        }
+        match self.next_document() {
+            Ok(Some(document)) => Some(self.options.deserialize(&document, None)),
+            Ok(None) => None,
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            },
+        }
+    }
+}
+
+/// Hands the next chunk of input to the splitter, returning whether there
+/// was any.
+fn read_more<R: Read>(reader: &mut R, splitter: &mut RecordSplitter) -> Result<bool> {
+    let mut chunk = [0; 8192];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(n) => {
+                splitter.extend(&chunk[..n]);
+                return Ok(n > 0);
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(error::io(err)),
+        }
+    }
+}
+
+fn parse_header(tag: &[u8], encoding: &str) -> Result<StreamHeader> {
+    // Closed so that the parser does not wait for the rest of the stream
+    let mut tag = tag.to_vec();
+    if !tag.ends_with(b"/>") {
+        tag.pop();
+        tag.extend_from_slice(b"/>");
+    }
+
+    let reader = DecodingReader::with_encoding(&tag[..], encoding)?;
+    for event in EventReader::new(reader) {
+        if let XmlEvent::StartElement { name, attributes, namespace } = event.map_err(error::reader)? {
+            let namespaces = namespace.0.into_iter()
+                .filter(|(prefix, uri)| {
+                    prefix != NS_XML_PREFIX && prefix != NS_XMLNS_PREFIX
+                        && !(prefix == NS_NO_PREFIX && uri.is_empty())
+                })
+                .collect();
+            return Ok(StreamHeader {
+                name: name.borrow().to_repr(),
+                attributes: attributes.into_iter().map(|a| (a.name.borrow().to_repr(), a.value)).collect(),
+                namespaces,
+            });
+        }
+    }
+    Err(error::with_message("missing root element".into()))
+}
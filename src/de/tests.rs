@@ -1,3 +1,5 @@
+#![allow(clippy::upper_case_acronyms)]
+
 use std::collections::HashMap;
 
 pub use super::from_str;
@@ -130,6 +132,43 @@ fn sequence() {
     assert_eq!(expected, actual);
 }
 
+#[test]
+fn sequence_item_attributes() {
+    setup();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Item {
+        #[serde(rename = "@id")]
+        id: u32,
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Document {
+        #[serde(rename = "item")]
+        items: Vec<Item>,
+    }
+
+    let expected = Document {
+        items: vec![
+            Item { id: 1, name: "first".to_string() },
+            Item { id: 2, name: "second".to_string() },
+            Item { id: 3, name: "third".to_string() },
+        ],
+    };
+
+    let input = r#"
+        <document>
+            <item id="1"><name>first</name></item>
+            <item id="2"><name>second</name></item>
+            <item id="3"><name>third</name></item>
+        </document>"#;
+
+    let actual: Document = from_str(input).unwrap();
+
+    assert_eq!(expected, actual);
+}
+
 #[test]
 fn unit_variant() {
     setup();
@@ -202,6 +241,37 @@ fn struct_variant() {
     assert_eq!(expected, actual);
 }
 
+#[test]
+fn root_variant() {
+    setup();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Message {
+        #[serde(rename = "@to")]
+        to: String,
+        body: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    enum Stanza {
+        Message(Message),
+        Ping { id: u32 },
+        Presence,
+    }
+
+    let input = r"<message to='juliet'><body>Wherefore</body></message>";
+    let expected = Stanza::Message(Message { to: "juliet".to_string(), body: "Wherefore".to_string() });
+    assert_eq!(expected, from_str::<Stanza>(input).unwrap());
+
+    let input = r"<ping><id>7</id></ping>";
+    assert_eq!(Stanza::Ping { id: 7 }, from_str::<Stanza>(input).unwrap());
+
+    assert_eq!(Stanza::Presence, from_str::<Stanza>("<presence/>").unwrap());
+
+    assert!(from_str::<Stanza>("<iq/>").is_err());
+}
+
 #[test]
 fn newtype_variant() {
     setup();
@@ -332,6 +402,36 @@ fn tuple() {
     assert_eq!(expected, actual);
 }
 
+#[test]
+fn tuple_typed_values() {
+    setup();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    enum Axis {
+        X, Y, Z,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Millimetres(f64);
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Document {
+        content: (Axis, Millimetres, char, bool),
+    }
+
+    let expected = Document {
+        content: (Axis::Y, Millimetres(1.5), 'a', true),
+    };
+
+    let input = r#"<document><content>y 1.5 a true</content></document>"#;
+
+    let actual: Document = from_str(input).unwrap();
+
+    assert_eq!(expected, actual);
+}
+
 #[test]
 fn types_char() {
     setup();
@@ -672,6 +772,1785 @@ mod attribute {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn optional_absent() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@count")]
+            count: Option<u32>,
+            content: String,
+        }
+
+        let expected = Document {
+            count: None,
+            content: "abc".to_string(),
+        };
+
+        let input = r#"<document><content>abc</content></document>"#;
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn optional_present() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@count")]
+            count: Option<u32>,
+            content: String,
+        }
+
+        let expected = Document {
+            count: Some(42),
+            content: "abc".to_string(),
+        };
+
+        let input = r#"<document count="42"><content>abc</content></document>"#;
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn typed_values() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        enum Status {
+            Active,
+            #[serde(rename = "on-hold")]
+            OnHold,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct OrderId(u32);
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Marker;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@status")]
+            status: Status,
+            #[serde(rename = "@previous")]
+            previous: Option<Status>,
+            #[serde(rename = "@id")]
+            id: OrderId,
+            #[serde(rename = "@grade")]
+            grade: char,
+            #[serde(rename = "@marker")]
+            marker: Marker,
+        }
+
+        let expected = Document {
+            status: Status::Active,
+            previous: Some(Status::OnHold),
+            id: OrderId(42),
+            grade: 'B',
+            marker: Marker,
+        };
+
+        let input = r#"<document status="active" previous="on-hold" id="42" grade="B" marker="" />"#;
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn unknown_variant() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        enum Status {
+            Active,
+            Inactive,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@status")]
+            status: Status,
+        }
+
+        let input = r#"<document status="archived" />"#;
+
+        assert!(from_str::<Document>(input).is_err());
+    }
+}
+
+mod list {
+    use super::*;
+
+    use crate::List;
+
+    #[test]
+    fn attribute() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@coords", with = "crate::list")]
+            coords: Vec<f64>,
+        }
+
+        let expected = Document {
+            coords: vec![1.5, 2.0, 3.25],
+        };
+
+        let input = r#"<document coords="1.5 2.0  3.25" />"#;
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn element() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            tags: List<String>,
+            sizes: List<u32>,
+        }
+
+        let expected = Document {
+            tags: List(vec!["red".to_string(), "green".to_string(), "blue".to_string()]),
+            sizes: List(vec![]),
+        };
+
+        let input = r#"
+            <document>
+                <tags>
+                    red green
+                    blue
+                </tags>
+                <sizes></sizes>
+            </document>"#;
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn plain_vec_attribute() {
+        setup();
+
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "document")]
+        struct Document {
+            #[serde(rename = "@coords")]
+            #[allow(dead_code)]
+            coords: Vec<f64>,
+        }
+
+        let err = from_str::<Document>(r#"<document coords="1.5 2.0" />"#).unwrap_err();
+        assert_eq!(
+            "a sequence cannot be read from text, use serde_xml::List for whitespace-separated items",
+            err.to_string());
+    }
+}
+
+mod bytes {
+    use super::*;
+
+    use serde::Deserialize;
+    use serde_bytes::ByteBuf;
+
+    use crate::{BinaryEncoding, Deserializer};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename = "document", rename_all = "kebab-case")]
+    struct Document {
+        #[serde(rename = "@hash", with = "serde_bytes")]
+        hash: Vec<u8>,
+        attachment: ByteBuf,
+    }
+
+    #[test]
+    fn base64() {
+        setup();
+
+        let expected = Document {
+            hash: vec![0xde, 0xad, 0xbe, 0xef],
+            attachment: ByteBuf::from(b"Hello, world!".to_vec()),
+        };
+
+        let input = indoc!(r#"
+            <document hash="3q2+7w==">
+              <attachment>
+                SGVsbG8s
+                IHdvcmxkIQ==
+              </attachment>
+            </document>"#);
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn hex() {
+        setup();
+
+        let expected = Document {
+            hash: vec![0xde, 0xad, 0xbe, 0xef],
+            attachment: ByteBuf::from(vec![]),
+        };
+
+        let input = r#"<document hash="DEADbeef"><attachment /></document>"#;
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap()
+            .with_binary_encoding(BinaryEncoding::Hex);
+        let actual = Document::deserialize(&mut de).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn invalid() {
+        setup();
+
+        let input = r#"<document hash="not base64!"><attachment /></document>"#;
+
+        assert!(from_str::<Document>(input).is_err());
+    }
+}
+
+mod lexical {
+    use super::*;
+
+    use serde::Deserialize;
+
+    use crate::{Deserializer, LexicalForm};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename = "document", rename_all = "kebab-case")]
+    struct Document {
+        #[serde(rename = "@enabled")]
+        enabled: bool,
+        #[serde(rename = "@count")]
+        count: i32,
+        high: f64,
+        low: f32,
+        missing: f64,
+        pair: (bool, u8),
+    }
+
+    #[test]
+    fn xsd() {
+        setup();
+
+        let input = r#"
+            <document enabled="1" count=" +5 ">
+                <high>INF</high>
+                <low>-INF</low>
+                <missing>NaN</missing>
+                <pair>0 +7</pair>
+            </document>"#;
+
+        let actual: Document = from_str(input).unwrap();
+
+        assert!(actual.enabled);
+        assert_eq!(5, actual.count);
+        assert_eq!(f64::INFINITY, actual.high);
+        assert_eq!(f32::NEG_INFINITY, actual.low);
+        assert!(actual.missing.is_nan());
+        assert_eq!((false, 7), actual.pair);
+    }
+
+    fn document(enabled: &str, count: &str, high: &str, missing: &str) -> String {
+        format!(r#"
+            <document enabled="{}" count="{}">
+                <high>{}</high>
+                <low>1</low>
+                <missing>{}</missing>
+                <pair>true 7</pair>
+            </document>"#, enabled, count, high, missing)
+    }
+
+    #[test]
+    fn xsd_rejects_rust_spellings() {
+        setup();
+
+        assert!(from_str::<Document>(&document("true", "5", "INF", "NaN")).is_ok());
+
+        assert!(from_str::<Document>(&document("true", "5", "inf", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "+inf", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "+INF", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "-inf", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "infinity", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "Infinity", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "INF", "nan")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "INF", "NAN")).is_err());
+        assert!(from_str::<Document>(&document("true", "5", "INF", "-NaN")).is_err());
+        assert!(from_str::<Document>(&document("true", "1_000", "INF", "NaN")).is_err());
+        assert!(from_str::<Document>(&document("True", "5", "INF", "NaN")).is_err());
+    }
+
+    #[test]
+    fn rust() {
+        setup();
+
+        let valid = r#"
+            <document enabled="true" count="5">
+                <high>inf</high>
+                <low>-1.5</low>
+                <missing>NaN</missing>
+                <pair>false 7</pair>
+            </document>"#;
+
+        let mut de = Deserializer::new_from_reader(valid.as_bytes()).unwrap()
+            .with_lexical_form(LexicalForm::Rust);
+        let actual = Document::deserialize(&mut de).unwrap();
+
+        assert!(actual.enabled);
+        assert_eq!(f64::INFINITY, actual.high);
+
+        let invalid = r#"
+            <document enabled="1" count="5">
+                <high>1</high>
+                <low>1</low>
+                <missing>1</missing>
+                <pair>false 7</pair>
+            </document>"#;
+
+        let mut de = Deserializer::new_from_reader(invalid.as_bytes()).unwrap()
+            .with_lexical_form(LexicalForm::Rust);
+
+        assert!(Document::deserialize(&mut de).is_err());
+    }
+}
+
+mod encoding {
+    use super::*;
+
+    use std::io::Read;
+
+    use crate::{from_reader, from_reader_with_encoding, from_reader_with_prolog, Prolog};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Payment {
+        #[serde(rename = "@currency")]
+        currency: String,
+        payee: String,
+    }
+
+    fn expected() -> Payment {
+        Payment { currency: "€".to_string(), payee: "Zoë".to_string() }
+    }
+
+    fn utf16(s: &str, bom: &[u8], to_bytes: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        let mut input = bom.to_vec();
+        for unit in s.encode_utf16() {
+            input.extend_from_slice(&to_bytes(unit));
+        }
+        input
+    }
+
+    #[test]
+    fn latin1() {
+        setup();
+
+        let input: &[u8] = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\
+            <payment currency=\"&#8364;\"><payee>Zo\xEB</payee></payment>";
+
+        let actual: Payment = from_reader(input).unwrap();
+
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn windows_1252() {
+        setup();
+
+        let input: &[u8] = b"<?xml version='1.0' encoding='windows-1252'?>\
+            <payment currency=\"\x80\"><payee>Zo\xEB</payee></payment>";
+
+        let actual: Payment = from_reader(input).unwrap();
+
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn declaration_across_reads() {
+        setup();
+
+        let input: &[u8] = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\
+            <payment currency=\"&#8364;\"><payee>Zo\xEB</payee></payment>";
+        let reader = (&input[..20]).chain(&input[20..]);
+
+        let actual: Payment = from_reader_with_encoding(reader, "ISO-8859-1").unwrap();
+        assert_eq!(expected(), actual);
+
+        let (prolog, _): (Prolog, Payment) = from_reader_with_prolog(input).unwrap();
+        assert_eq!("ISO-8859-1", prolog.encoding);
+    }
+
+    #[test]
+    fn utf8_bom() {
+        setup();
+
+        let input: &[u8] = b"\xEF\xBB\xBF<payment currency=\"\xE2\x82\xAC\"><payee>Zo\xC3\xAB</payee></payment>";
+
+        let actual: Payment = from_reader(input).unwrap();
+
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn utf16_bom() {
+        setup();
+
+        let document = r#"<?xml version="1.0" encoding="UTF-16"?><payment currency="€"><payee>Zoë</payee></payment>"#;
+
+        let actual: Payment = from_reader(&utf16(document, b"\xFF\xFE", u16::to_le_bytes)[..]).unwrap();
+        assert_eq!(expected(), actual);
+
+        let actual: Payment = from_reader(&utf16(document, b"\xFE\xFF", u16::to_be_bytes)[..]).unwrap();
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn override_declaration() {
+        setup();
+
+        let input: &[u8] = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <payment currency=\"\x80\"><payee>Zo\xEB</payee></payment>";
+
+        assert!(from_reader::<_, Payment>(input).is_err());
+
+        let actual: Payment = from_reader_with_encoding(input, "windows-1252").unwrap();
+
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn unsupported() {
+        setup();
+
+        let input: &[u8] = b"<?xml version=\"1.0\" encoding=\"EBCDIC-XYZ\"?><payment/>";
+
+        assert!(from_reader::<_, Payment>(input).is_err());
+    }
+
+    #[test]
+    fn string_ignores_declaration() {
+        setup();
+
+        let input = r#"<?xml version="1.0" encoding="ISO-8859-1"?><payment currency="€"><payee>Zoë</payee></payment>"#;
+
+        let actual: Payment = from_str(input).unwrap();
+
+        assert_eq!(expected(), actual);
+    }
+}
+
+mod prolog {
+    use super::*;
+
+    use crate::{from_reader_with_prolog, from_str_with_prolog, DecodingReader, Deserializer};
+    use crate::{Prolog, ProcessingInstruction, XmlVersion};
+
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename = "OFX")]
+    struct Document {
+        value: String,
+    }
+
+    fn expected() -> Document {
+        Document { value: "plain text".to_string() }
+    }
+
+    #[test]
+    fn declaration_and_instructions() {
+        setup();
+
+        let input = indoc!(r#"
+            <?xml version="1.1" encoding="UTF-8" standalone="no"?>
+            <?OFX OFXHEADER="200" VERSION="203"?>
+            <?xml-stylesheet href="ofx.css"?>
+            <OFX><value>plain text</value></OFX>"#);
+
+        let (prolog, actual): (Prolog, Document) = from_str_with_prolog(input).unwrap();
+
+        let expected_prolog = Prolog {
+            version: XmlVersion::Version11,
+            encoding: "UTF-8".to_string(),
+            standalone: Some(false),
+            processing_instructions: vec![
+                ProcessingInstruction::new("OFX", Some(r#"OFXHEADER="200" VERSION="203""#)),
+                ProcessingInstruction::new("xml-stylesheet", Some(r#"href="ofx.css""#)),
+            ],
+            doctype: None,
+        };
+        assert_eq!(expected_prolog, prolog);
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn doctype() {
+        setup();
+
+        let input = indoc!(r#"
+            <?xml version="1.0"?>
+            <!-- <!DOCTYPE commented> -->
+            <!DOCTYPE OFX SYSTEM "ofx.dtd" [
+              <!ENTITY version "2.0.3">
+              <!ATTLIST OFX note CDATA "[draft]">
+            ]>
+            <OFX><value>plain text</value></OFX>"#);
+
+        let (prolog, actual): (Prolog, Document) = from_reader_with_prolog(input.as_bytes()).unwrap();
+
+        let expected_doctype = indoc!(r#"
+            OFX SYSTEM "ofx.dtd" [
+              <!ENTITY version "2.0.3">
+              <!ATTLIST OFX note CDATA "[draft]">
+            ]"#);
+        assert_eq!(Some(expected_doctype.to_string()), prolog.doctype);
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn without_declaration() {
+        setup();
+
+        let input = r"<OFX><value>plain text</value></OFX>";
+
+        let (prolog, actual): (Prolog, Document) = from_str_with_prolog(input).unwrap();
+
+        assert_eq!(Prolog::default(), prolog);
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn before_deserializing() {
+        setup();
+
+        let input = r#"<?OFX VERSION="203"?><OFX><value>plain text</value></OFX>"#;
+
+        let mut de = Deserializer::new_decoding(DecodingReader::new(input.as_bytes())).unwrap();
+        let version = de.prolog().unwrap().processing_instructions[0].data.clone();
+        let actual = Document::deserialize(&mut de).unwrap();
+
+        assert_eq!(Some(r#"VERSION="203""#.to_string()), version);
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn after_deserializing() {
+        setup();
+
+        let input = r#"<!DOCTYPE OFX SYSTEM "ofx.dtd"><OFX><value>plain text</value></OFX>"#;
+
+        let mut de = Deserializer::new_decoding(DecodingReader::new(input.as_bytes())).unwrap();
+        let actual = Document::deserialize(&mut de).unwrap();
+
+        assert_eq!(Some(r#"OFX SYSTEM "ofx.dtd""#), de.prolog().unwrap().doctype.as_deref());
+        assert_eq!(expected(), actual);
+    }
+
+    #[test]
+    fn after_long_comments() {
+        setup();
+
+        let comment = format!("<!-- {} -->", "a".repeat(40_000));
+        let input = format!(r#"{}{}<!DOCTYPE OFX SYSTEM "ofx.dtd"><OFX><value>plain text</value></OFX>"#, comment, comment);
+
+        let (prolog, _): (Prolog, Document) = from_reader_with_prolog(input.as_bytes()).unwrap();
+
+        assert_eq!(Some(r#"OFX SYSTEM "ofx.dtd""#.to_string()), prolog.doctype);
+    }
+
+    #[test]
+    fn oversized_doctype() {
+        setup();
+
+        let input = format!(r#"<!DOCTYPE OFX [<!-- {} -->]><OFX><value>plain text</value></OFX>"#, "a".repeat(70_000));
+
+        let err = from_reader_with_prolog::<_, Document>(input.as_bytes()).unwrap_err();
+
+        assert!(err.to_string().contains("document type declaration longer than"), "{}", err);
+    }
+}
+
+mod cdata {
+    use super::*;
+
+    use crate::CData;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Page {
+        body: CData<String>,
+        script: String,
+    }
+
+    #[test]
+    fn either_form() {
+        setup();
+
+        let expected = Page {
+            body: CData("<p>Fish & chips</p>".to_string()),
+            script: "a]]>b".to_string(),
+        };
+
+        let input = r"<page><body>&lt;p>Fish &amp; chips&lt;/p></body><script><![CDATA[a]]]]><![CDATA[>b]]></script></page>";
+        assert_eq!(expected, from_str::<Page>(input).unwrap());
+
+        let input = r"<page><body><![CDATA[<p>Fish & chips</p>]]></body><script>a]]&gt;b</script></page>";
+        assert_eq!(expected, from_str::<Page>(input).unwrap());
+    }
+}
+
+mod comment {
+    use super::*;
+
+    use crate::Deserializer;
+
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        #[serde(rename = "#comment")]
+        comment: Option<String>,
+        server: Server,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        #[serde(rename = "#comment")]
+        comment: Option<String>,
+        port: u16,
+    }
+
+    const INPUT: &str = r"<config>
+        <!-- Generated -->
+        <server>
+            <!-- Public -->
+            <port>80<!-- inline -->80</port>
+        </server>
+    </config>";
+
+    #[test]
+    fn skipped() {
+        setup();
+
+        let expected = Config { comment: None, server: Server { comment: None, port: 8080 } };
+
+        assert_eq!(expected, from_str::<Config>(INPUT).unwrap());
+    }
+
+    #[test]
+    fn captured() {
+        setup();
+
+        let expected = Config {
+            comment: Some("Generated".to_string()),
+            server: Server { comment: Some("Public".to_string()), port: 8080 },
+        };
+
+        let mut de = Deserializer::new_from_reader(INPUT.as_bytes()).unwrap().with_comments(true);
+        let actual = Config::deserialize(&mut de).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn not_attributed_to_parent() {
+        setup();
+
+        let input = r"<config><server><port>80</port><!-- inner --></server></config>";
+
+        let expected = Config { comment: None, server: Server { comment: Some("inner".to_string()), port: 80 } };
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap().with_comments(true);
+        let actual = Config::deserialize(&mut de).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn several_joined() {
+        setup();
+
+        let input = r"<server><!-- one --><port>80</port><!-- two --></server>";
+
+        let expected = Server { comment: Some("one\ntwo".to_string()), port: 80 };
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap().with_comments(true);
+        let actual = Server::deserialize(&mut de).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn several_collected() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            #[serde(rename = "#comment", default)]
+            comments: Vec<String>,
+            port: u16,
+        }
+
+        let input = r"<server><!-- one --><port>80</port><!-- two --></server>";
+
+        let expected = Server { comments: vec!["one".to_string(), "two".to_string()], port: 80 };
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap().with_comments(true);
+        let actual = Server::deserialize(&mut de).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn denied_without_field() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Server {
+            port: u16,
+        }
+
+        let input = r"<server><!-- one --><port>80</port></server>";
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap().with_comments(true);
+        let actual = Server::deserialize(&mut de).unwrap();
+
+        assert_eq!(Server { port: 80 }, actual);
+    }
+
+    #[test]
+    fn ignored_without_field() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            port: u16,
+        }
+
+        let input = r"<server><!-- one --><port>80</port><!-- two --></server>";
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap().with_comments(true);
+        let actual = Server::deserialize(&mut de).unwrap();
+
+        assert_eq!(Server { port: 80 }, actual);
+    }
+}
+
+mod map_entries {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::{Deserializer, MapRepresentation};
+
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Report<K: Ord> {
+        totals: BTreeMap<K, u32>,
+    }
+
+    fn from_entries<'de, T: Deserialize<'de>>(input: &str, representation: MapRepresentation) -> ::Result<T> {
+        let mut de = Deserializer::new_from_reader(input.as_bytes())?.with_map_representation(representation);
+        T::deserialize(&mut de)
+    }
+
+    #[test]
+    fn entries() {
+        setup();
+
+        let input = r#"<report><totals><entry key="2024 Q1">10</entry><entry key="a/b">20</entry></totals></report>"#;
+
+        let mut totals = BTreeMap::new();
+        totals.insert("2024 Q1".to_string(), 10);
+        totals.insert("a/b".to_string(), 20);
+
+        let actual: Report<String> = from_entries(input, MapRepresentation::entries()).unwrap();
+
+        assert_eq!(Report { totals }, actual);
+    }
+
+    #[test]
+    fn typed_keys() {
+        setup();
+
+        let input = r#"<report><totals><total year="2023">10</total><total year="2024">20</total></totals></report>"#;
+
+        let mut totals = BTreeMap::new();
+        totals.insert(2023, 10);
+        totals.insert(2024, 20);
+
+        let representation = MapRepresentation::Entries { element: "total".to_string(), key: "year".to_string() };
+        let actual: Report<u16> = from_entries(input, representation).unwrap();
+
+        assert_eq!(Report { totals }, actual);
+    }
+
+    #[test]
+    fn unknown_elements() {
+        setup();
+
+        let input = r#"<report><extra><x>1</x></extra><totals><entry key="a">10</entry></totals></report>"#;
+
+        let mut totals = BTreeMap::new();
+        totals.insert("a".to_string(), 10);
+
+        let actual: Report<String> = from_entries(input, MapRepresentation::entries()).unwrap();
+
+        assert_eq!(Report { totals }, actual);
+    }
+
+    #[test]
+    fn flattened_struct() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Named {
+            #[serde(rename = "@id")]
+            id: String,
+            name: String,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Labelled {
+            #[serde(flatten)]
+            named: Named,
+            totals: BTreeMap<String, u32>,
+        }
+
+        let input = r#"<labelled id="7"><name>Q1</name><totals><entry key="a">10</entry></totals></labelled>"#;
+
+        let mut totals = BTreeMap::new();
+        totals.insert("a".to_string(), 10);
+
+        let actual: Labelled = from_entries(input, MapRepresentation::entries()).unwrap();
+
+        assert_eq!(Labelled { named: Named { id: "7".to_string(), name: "Q1".to_string() }, totals }, actual);
+    }
+
+    #[test]
+    fn expecting_struct() {
+        setup();
+
+        use std::fmt;
+        use serde::de::{MapAccess, Visitor};
+
+        /// A map whose visitor describes it as a struct.
+        #[derive(Debug, PartialEq)]
+        struct Totals(BTreeMap<String, u32>);
+
+        impl<'de> Deserialize<'de> for Totals {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct TotalsVisitor;
+
+                impl<'de> Visitor<'de> for TotalsVisitor {
+                    type Value = Totals;
+
+                    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                        formatter.write_str("struct Totals")
+                    }
+
+                    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Totals, A::Error> {
+                        let mut totals = BTreeMap::new();
+                        while let Some((key, value)) = map.next_entry()? {
+                            totals.insert(key, value);
+                        }
+                        Ok(Totals(totals))
+                    }
+                }
+
+                deserializer.deserialize_map(TotalsVisitor)
+            }
+        }
+
+        let input = r#"<totals><entry key="a">10</entry></totals>"#;
+
+        let mut totals = BTreeMap::new();
+        totals.insert("a".to_string(), 10);
+
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap()
+            .with_map_representation(MapRepresentation::entries());
+        assert_eq!(Totals(totals), Totals::deserialize(&mut de).unwrap());
+    }
+
+    #[test]
+    fn invalid_entries() {
+        setup();
+
+        let missing_key = r#"<report><totals><entry>10</entry></totals></report>"#;
+        assert!(from_entries::<Report<String>>(missing_key, MapRepresentation::entries()).is_err());
+
+        let other_element = r#"<report><totals><item key="a">10</item></totals></report>"#;
+        assert!(from_entries::<Report<String>>(other_element, MapRepresentation::entries()).is_err());
+    }
+}
+
+mod stanzas {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::{self, Read};
+    use super::super::{StanzaReader, StreamHeader};
+    use lexical::LexicalForm;
+    use limits::{Limit, Limits};
+
+    /// Hands out one chunk per read, and fails once they are all read, as a
+    /// connection would block.
+    struct Connection {
+        chunks: VecDeque<&'static str>,
+    }
+
+    impl Connection {
+        fn new(chunks: &[&'static str]) -> Self {
+            Connection { chunks: chunks.iter().cloned().collect() }
+        }
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let chunk = self.chunks.pop_front()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::WouldBlock, "no more input"))?;
+            buf[..chunk.len()].copy_from_slice(chunk.as_bytes());
+            Ok(chunk.len())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Message {
+        #[serde(rename = "@to")]
+        to: String,
+        body: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Presence {
+        show: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Stanza {
+        #[serde(rename = "message")]
+        Message(Message),
+        #[serde(rename = "presence")]
+        Presence(Presence),
+    }
+
+    const HEADER: &str = concat!(
+        "<?xml version='1.0'?>",
+        "<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' ",
+        "to='example.com' version='1.0'>");
+
+    #[test]
+    fn header() {
+        setup();
+
+        let mut reader = StanzaReader::new(Connection::new(&[&HEADER[..30], &HEADER[30..]]));
+
+        let expected = StreamHeader {
+            name: "stream:stream".to_string(),
+            attributes: vec![
+                ("to".to_string(), "example.com".to_string()),
+                ("version".to_string(), "1.0".to_string()),
+            ],
+            namespaces: vec![
+                ("".to_string(), "jabber:client".to_string()),
+                ("stream".to_string(), "http://etherx.jabber.org/streams".to_string()),
+            ],
+        };
+        assert_eq!(&expected, reader.header().unwrap());
+    }
+
+    #[test]
+    fn without_waiting() {
+        setup();
+
+        let mut reader = StanzaReader::new(Connection::new(&[
+            HEADER,
+            "<message to='juliet@example.com'><bo",
+            "dy>Art thou not Romeo?</body></message> <pres",
+        ]));
+
+        let expected = Message { to: "juliet@example.com".to_string(), body: "Art thou not Romeo?".to_string() };
+        assert_eq!(Some(expected), reader.next_stanza().unwrap());
+        assert_eq!("stream:stream", reader.header().unwrap().name);
+
+        let err = reader.next_stanza::<Presence>().unwrap_err();
+        assert!(err.to_string().contains("no more input"));
+    }
+
+    #[test]
+    fn enum_stanzas() {
+        setup();
+
+        let mut reader = StanzaReader::new(Connection::new(&[
+            HEADER,
+            "<presence><show>away</show></presence><presence/>",
+            "<message to='romeo@example.net'><body>Neither</body></message>",
+            "</stream:stream>",
+        ]));
+
+        let mut stanzas = Vec::new();
+        while let Some(stanza) = reader.next_stanza::<Stanza>().unwrap() {
+            stanzas.push(stanza);
+        }
+
+        let expected = vec![
+            Stanza::Presence(Presence { show: Some("away".to_string()) }),
+            Stanza::Presence(Presence { show: None }),
+            Stanza::Message(Message { to: "romeo@example.net".to_string(), body: "Neither".to_string() }),
+        ];
+        assert_eq!(expected, stanzas);
+        assert_eq!(None, reader.next_stanza::<Stanza>().unwrap());
+    }
+
+    #[test]
+    fn end_of_stream() {
+        setup();
+
+        let mut reader = StanzaReader::new("<stream><message to='a'><body>".as_bytes());
+        let err = reader.next_stanza::<Message>().unwrap_err();
+        assert!(err.to_string().contains("middle of a stanza"), "{}", err);
+
+        let mut reader = StanzaReader::new("<stream/>".as_bytes());
+        assert_eq!("stream", reader.header().unwrap().name);
+        assert_eq!(None, reader.next_stanza::<Message>().unwrap());
+    }
+
+    #[test]
+    fn options() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Ping {
+            urgent: bool,
+        }
+
+        let input = "<stream><ping><urgent>1</urgent></ping><ping><urgent>1</urgent></ping>";
+        let mut reader = StanzaReader::new(input.as_bytes());
+        assert_eq!(Some(Ping { urgent: true }), reader.next_stanza().unwrap());
+        let mut reader = reader.with_lexical_form(LexicalForm::Rust);
+        assert!(reader.next_stanza::<Ping>().is_err());
+
+        let input = "<stream><ping><urgent>1</urgent></ping>";
+        let limits = Limits { max_depth: 1, ..Limits::default() };
+        let mut reader = StanzaReader::new(input.as_bytes()).with_limits(limits);
+        let err = reader.next_stanza::<Ping>().unwrap_err();
+        assert_eq!(Some(Limit::Depth), err.limit());
+    }
+
+    #[test]
+    fn entities() {
+        setup();
+
+        let input = "<stream><message to='juliet'><body>&greeting;, Juliet</body></message>";
+        assert!(StanzaReader::new(input.as_bytes()).next_stanza::<Message>().is_err());
+
+        let mut reader = StanzaReader::new(input.as_bytes()).with_entities(vec![("greeting", "Good morrow")]);
+        let expected = Message { to: "juliet".to_string(), body: "Good morrow, Juliet".to_string() };
+        assert_eq!(Some(expected), reader.next_stanza().unwrap());
+    }
+
+    #[test]
+    fn oversized_stanza() {
+        setup();
+
+        let body = "a".repeat(10_000);
+        let input = format!("<stream><message to='a'><body>{}</body></message>", body);
+        let limits = Limits { max_input_bytes: 1_000, ..Limits::default() };
+        let mut reader = StanzaReader::new(input.as_bytes()).with_limits(limits);
+
+        let err = reader.next_stanza::<Message>().unwrap_err();
+        assert_eq!(Some(Limit::InputBytes), err.limit());
+    }
+}
+
+mod documents {
+    use super::*;
+    use std::io::{self, Read};
+    use super::super::StreamDeserializer;
+    use lexical::LexicalForm;
+    use limits::{Limit, Limits};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Entry {
+        #[serde(rename = "@level")]
+        level: String,
+        message: String,
+    }
+
+    fn entry(level: &str, message: &str) -> Entry {
+        Entry { level: level.to_string(), message: message.to_string() }
+    }
+
+    /// Hands out one byte per read.
+    struct Slow<'a>(&'a [u8]);
+
+    impl<'a> Read for Slow<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&first, rest)) if !buf.is_empty() => {
+                    buf[0] = first;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        setup();
+
+        let first = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<entry level=\"info\"><message>started</message></entry>";
+        let second = "\n<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><!-- shipped -->\n<entry level=\"warn\"><message>caf\u{E9}</message></entry>\n";
+        let mut input = first.as_bytes().to_vec();
+        input.extend(second.chars().map(|c| c as u8));
+
+        let mut entries = StreamDeserializer::<_, Entry>::new(Slow(&input));
+        assert_eq!(entry("info", "started"), entries.next().unwrap().unwrap());
+        assert_eq!(first.len(), entries.byte_offset());
+        assert_eq!(entry("warn", "caf\u{E9}"), entries.next().unwrap().unwrap());
+        assert_eq!(input.len() - 1, entries.byte_offset());
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn fragment() {
+        setup();
+
+        let input = r#"<entry level="info"><message>a</message></entry><entry level="debug"><message>b</message></entry>
+            <entry level="error"><message><![CDATA[</entry>]]></message></entry>"#;
+
+        let entries: Vec<Entry> = StreamDeserializer::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(vec![entry("info", "a"), entry("debug", "b"), entry("error", "</entry>")], entries);
+    }
+
+    #[test]
+    fn invalid_document() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Count {
+            value: u32,
+        }
+
+        let input = "<count><value>1</value></count><count><value>x</value></count><count><value>3</value></count>";
+        let counts: Vec<_> = StreamDeserializer::<_, Count>::new(input.as_bytes()).collect();
+
+        assert_eq!(3, counts.len());
+        assert_eq!(Count { value: 1 }, *counts[0].as_ref().unwrap());
+        assert!(counts[1].is_err());
+        assert_eq!(Count { value: 3 }, *counts[2].as_ref().unwrap());
+    }
+
+    #[test]
+    fn malformed_stream() {
+        setup();
+
+        let mut entries = StreamDeserializer::<_, Entry>::new(
+            r#"<entry level="info"><message>a</message></entry> oops"#.as_bytes());
+        assert!(entries.next().unwrap().is_ok());
+        let err = entries.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("byte 49"), "{}", err);
+        assert!(entries.next().is_none());
+
+        let mut entries = StreamDeserializer::<_, Entry>::new(
+            r#"<entry level="info"><message>a</message></entry><entry level="info"><mess"#.as_bytes());
+        assert!(entries.next().unwrap().is_ok());
+        let err = entries.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("starting at byte 48"), "{}", err);
+    }
+
+    #[test]
+    fn options() {
+        setup();
+
+        let input = "<entry level='info'><message>a</message></entry><entry level='warn'><message>abcde</message></entry>";
+        let limits = Limits { max_text_length: 4, ..Limits::default() };
+        let mut entries = StreamDeserializer::<_, Entry>::new(input.as_bytes()).with_limits(limits);
+
+        assert_eq!(entry("info", "a"), entries.next().unwrap().unwrap());
+        assert_eq!(Some(Limit::TextLength), entries.next().unwrap().unwrap_err().limit());
+        assert!(entries.next().is_none());
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Flag {
+            value: bool,
+        }
+
+        let input = "<flag><value>true</value></flag><flag><value>1</value></flag>";
+        let mut flags = StreamDeserializer::<_, Flag>::new(input.as_bytes()).with_lexical_form(LexicalForm::Rust);
+        assert_eq!(Flag { value: true }, flags.next().unwrap().unwrap());
+        assert!(flags.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn entities() {
+        setup();
+
+        let input = concat!(
+            r#"<entry level="info"><message>&app; started</message></entry>"#,
+            r#"<!DOCTYPE entry [<!ENTITY motd SYSTEM "motd.txt">]>"#,
+            r#"<entry level="info"><message>&app; says &motd;</message></entry>"#);
+
+        let entries: Vec<Entry> = StreamDeserializer::new(input.as_bytes())
+            .with_entities(vec![("app", "server")])
+            .with_entity_resolver(|_: Option<&str>, system_id: &str| match system_id {
+                "motd.txt" => Ok("hello".to_string()),
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, system_id.to_string())),
+            })
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(vec![entry("info", "server started"), entry("info", "server says hello")], entries);
+    }
+
+    #[test]
+    fn trailing_markup() {
+        setup();
+
+        let input = concat!(
+            "<?xml version='1.0'?><entry level='info'><message>a</message></entry><!-- end of a --><?done a?>\n",
+            "<?xml version='1.0'?><?start b?><entry level='info'><message>b</message></entry><!-- end of b -->",
+            "<entry level='info'><message>c</message></entry>");
+
+        let mut entries = StreamDeserializer::<_, Entry>::new(input.as_bytes());
+        assert_eq!(entry("info", "a"), entries.next().unwrap().unwrap());
+        assert_eq!(entry("info", "b"), entries.next().unwrap().unwrap());
+        assert_eq!(entry("info", "c"), entries.next().unwrap().unwrap());
+        assert!(entries.next().is_none());
+    }
+}
+
+mod limits {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use super::super::{DecodingReader, Deserializer};
+    use limits::{Limit, Limits};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Node {
+        #[serde(rename = "@id")]
+        id: Option<String>,
+        text: Option<String>,
+        node: Option<Box<Node>>,
+    }
+
+    fn with_limits<T: DeserializeOwned>(input: &str, limits: Limits) -> ::error::Result<T> {
+        let reader = DecodingReader::new(input.as_bytes());
+        let mut de = Deserializer::new_decoding(reader)?.with_limits(limits);
+        T::deserialize(&mut de)
+    }
+
+    fn nested(depth: usize) -> String {
+        format!("{}{}", "<node>".repeat(depth), "</node>".repeat(depth))
+    }
+
+    #[test]
+    fn depth_by_default() {
+        setup();
+
+        assert!(from_str::<Node>(&nested(100)).is_ok());
+
+        let err = from_str::<Node>(&nested(100_000)).unwrap_err();
+        assert_eq!(Some(Limit::Depth), err.limit());
+        assert_eq!("element depth limit of 128 exceeded", err.to_string());
+    }
+
+    #[test]
+    fn attributes() {
+        setup();
+
+        let limits = Limits { max_attributes: 2, ..Limits::default() };
+        assert!(with_limits::<Node>(r#"<node id="1" a="2"/>"#, limits).is_ok());
+
+        let err = with_limits::<Node>(r#"<node id="1" a="2" b="3"/>"#, limits).unwrap_err();
+        assert_eq!(Some(Limit::Attributes), err.limit());
+    }
+
+    #[test]
+    fn text_length() {
+        setup();
+
+        let limits = Limits { max_text_length: 4, ..Limits::default() };
+        assert!(with_limits::<Node>("<node><text>abcd</text></node>", limits).is_ok());
+
+        let err = with_limits::<Node>("<node><text>abcde</text></node>", limits).unwrap_err();
+        assert_eq!(Some(Limit::TextLength), err.limit());
+
+        let err = with_limits::<Node>("<node><text>ab<!-- -->cde</text></node>", limits).unwrap_err();
+        assert_eq!(Some(Limit::TextLength), err.limit());
+
+        let err = with_limits::<Node>(r#"<node id="abcde"/>"#, limits).unwrap_err();
+        assert_eq!(Some(Limit::TextLength), err.limit());
+    }
+
+    #[test]
+    fn elements() {
+        setup();
+
+        let limits = Limits { max_elements: 3, ..Limits::default() };
+        assert!(with_limits::<Node>("<node><node/><text>a</text></node>", limits).is_ok());
+
+        let err = with_limits::<Node>("<node><node/><text>a</text><ignored/></node>", limits).unwrap_err();
+        assert_eq!(Some(Limit::Elements), err.limit());
+    }
+
+    #[test]
+    fn input_bytes() {
+        setup();
+
+        let input = format!("<node><text>{}</text></node>", "a".repeat(100_000));
+        let limits = Limits { max_input_bytes: 50_000, ..Limits::default() };
+
+        let err = with_limits::<Node>(&input, limits).unwrap_err();
+        assert_eq!(Some(Limit::InputBytes), err.limit());
+        assert!(with_limits::<Node>(&input, Limits::default()).is_ok());
+    }
+
+    #[test]
+    fn input_bytes_from_reader() {
+        setup();
+
+        let input = format!("<node><text>{}</text></node>", "a".repeat(100_000));
+        let limits = Limits { max_input_bytes: 50_000, ..Limits::default() };
+        let mut de = Deserializer::new_from_reader(input.as_bytes()).unwrap().with_limits(limits);
+
+        let err = <Node as serde::Deserialize>::deserialize(&mut de).unwrap_err();
+        assert_eq!(Some(Limit::InputBytes), err.limit());
+    }
+
+    #[test]
+    fn unlimited() {
+        setup();
+
+        let input = nested(200);
+        assert_eq!(Some(Limit::Depth), from_str::<Node>(&input).unwrap_err().limit());
+        assert!(with_limits::<Node>(&input, Limits::unlimited()).is_ok());
+    }
+}
+
+mod entities {
+    use super::*;
+    use std::fs;
+    use std::io::{self, Read};
+    use serde::Deserialize;
+    use serde::de::DeserializeOwned;
+    use super::super::{from_reader, from_str_with_prolog, DecodingReader, Deserializer};
+    use entities::DirectoryResolver;
+    use limits::{Limit, Limits};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Note {
+        #[serde(rename = "@from")]
+        from: Option<String>,
+        body: String,
+    }
+
+    fn read<T: DeserializeOwned>(reader: DecodingReader<&[u8]>) -> ::error::Result<T> {
+        T::deserialize(&mut Deserializer::new_decoding(reader)?)
+    }
+
+    /// Hands out a byte at a time, splitting every reference.
+    struct OneByte<'a>(&'a [u8]);
+
+    impl<'a> Read for OneByte<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn internal_subset() {
+        setup();
+
+        let input = indoc!(r#"
+            <?xml version="1.0"?>
+            <!DOCTYPE note [
+              <!-- <!ENTITY product "Gadget"> -->
+              <!ENTITY product "Widget">
+              <!ENTITY product "Gadget">
+              <!ENTITY version "2">
+              <!ENTITY name "&product; &version; &lt;3">
+              <!ENTITY % param "ignored">
+            ]>
+            <note from="&product;"><body>&name; &amp; &#65;</body></note>"#);
+
+        let expected = Note { from: Some("Widget".to_string()), body: "Widget 2 <3 & A".to_string() };
+        let note: Note = from_str(input).unwrap();
+        assert_eq!(expected, note);
+
+        let note: Note = from_reader(OneByte(input.as_bytes())).unwrap();
+        assert_eq!(expected, note);
+
+        let (prolog, _) = from_str_with_prolog::<Note>(input).unwrap();
+        assert!(prolog.doctype.unwrap().contains(r#"<!ENTITY version "2">"#));
+    }
+
+    #[test]
+    fn unquoted_apostrophes() {
+        setup();
+
+        let input = r#"<!DOCTYPE a [ <!-- it's --> <!ELEMENT a ANY> ]><a/>"#;
+        let (prolog, _) = from_str_with_prolog::<()>(input).unwrap();
+        assert_eq!(Some("a [ <!-- it's --> <!ELEMENT a ANY> ]".to_string()), prolog.doctype);
+
+        let input = r#"<!DOCTYPE note [ <?pi don't?> <!ENTITY product "Widget"> ]><note><body>&product;</body></note>"#;
+        let note: Note = from_reader(OneByte(input.as_bytes())).unwrap();
+        assert_eq!(Note { from: None, body: "Widget".to_string() }, note);
+    }
+
+    #[test]
+    fn markup() {
+        setup();
+
+        let input = r#"<!DOCTYPE note [<!ENTITY signature "<from>Jane</from>">]><note><body>Hi</body>&signature;</note>"#;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Signed {
+            body: String,
+            from: String,
+        }
+        let note: Signed = from_str(input).unwrap();
+        assert_eq!(Signed { body: "Hi".to_string(), from: "Jane".to_string() }, note);
+    }
+
+    #[test]
+    fn extra_entities() {
+        setup();
+
+        let input = r#"<!DOCTYPE note [<!ENTITY copy "(c)">]><note from="a&nbsp;b"><body>&copy;&nbsp;2024</body></note>"#;
+        let reader = DecodingReader::new(input.as_bytes())
+            .with_entities(vec![("nbsp", "\u{A0}"), ("copy", "\u{A9}")]);
+        let note: Note = read(reader).unwrap();
+
+        assert_eq!(Note { from: Some("a\u{A0}b".to_string()), body: "(c)\u{A0}2024".to_string() }, note);
+
+        assert!(from_str::<Note>("<note><body>&nbsp;</body></note>").is_err());
+    }
+
+    #[test]
+    fn extra_entities_are_text() {
+        setup();
+
+        let input = r#"<note from="&tag;"><body>&tag; &amp2;</body></note>"#;
+        let reader = DecodingReader::new(input.as_bytes())
+            .with_entities(vec![("tag", "<b>\"x\"</b>"), ("amp2", "&amp;&")]);
+        let note: Note = read(reader).unwrap();
+
+        let expected = Note { from: Some("<b>\"x\"</b>".to_string()), body: "<b>\"x\"</b> &amp;&".to_string() };
+        assert_eq!(expected, note);
+    }
+
+    #[test]
+    fn external_refused() {
+        setup();
+
+        let input = r#"<!DOCTYPE note [<!ENTITY secret SYSTEM "file:///etc/passwd">]><note><body>&secret;</body></note>"#;
+        let err = from_str::<Note>(input).unwrap_err();
+        assert!(err.to_string().contains("EntityResolver"), "{}", err);
+
+        // Declaring it is harmless
+        let input = r#"<!DOCTYPE note [<!ENTITY secret SYSTEM "file:///etc/passwd">]><note><body>-</body></note>"#;
+        assert!(from_str::<Note>(input).is_ok());
+    }
+
+    #[test]
+    fn resolver() {
+        setup();
+
+        let input = indoc!(r#"
+            <!DOCTYPE note SYSTEM "note.dtd" [
+              <!ENTITY chapter PUBLIC "-//Example//Chapter" "chapter.xml">
+            ]>
+            <note><body>&chapter; &product;</body></note>"#);
+        let reader = DecodingReader::new(input.as_bytes())
+            .with_entity_resolver(|public_id: Option<&str>, system_id: &str| match system_id {
+                "note.dtd" => Ok(r#"<!ENTITY product "Widget">"#.to_string()),
+                "chapter.xml" => {
+                    assert_eq!(Some("-//Example//Chapter"), public_id);
+                    Ok("<?xml version='1.0' encoding='utf-8'?>Chapter 1".to_string())
+                },
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, system_id.to_string())),
+            });
+        let note: Note = read(reader).unwrap();
+
+        assert_eq!("Chapter 1 Widget", note.body);
+    }
+
+    #[test]
+    fn directory_resolver() {
+        setup();
+
+        let root = ::std::env::temp_dir().join(format!("serde_xml_entities_{}", ::std::process::id()));
+        fs::create_dir_all(root.join("parts")).unwrap();
+        fs::write(root.join("parts/body.txt"), "from a file").unwrap();
+
+        let read_entity = |system_id: &str| {
+            let input = format!(r#"<!DOCTYPE note [<!ENTITY body SYSTEM "{}">]><note><body>&body;</body></note>"#, system_id);
+            let reader = DecodingReader::new(input.as_bytes())
+                .with_entity_resolver(DirectoryResolver::new(&root));
+            read::<Note>(reader).map(|note| note.body)
+        };
+
+        assert_eq!("from a file", read_entity("parts/body.txt").unwrap());
+        assert_eq!("from a file", read_entity("./parts/body.txt").unwrap());
+        assert!(read_entity("parts/../../body.txt").is_err());
+        assert!(read_entity("../body.txt").is_err());
+        assert!(read_entity("/etc/passwd").is_err());
+        assert!(read_entity("file:///etc/passwd").is_err());
+        assert!(read_entity("http://example.com/body.txt").is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn expansion_limit() {
+        setup();
+
+        let mut input = String::from(r#"<!DOCTYPE note [<!ENTITY lol0 "lol">"#);
+        for i in 1..10 {
+            input.push_str(&format!(r#"<!ENTITY lol{} "{}">"#, i, format!("&lol{};", i - 1).repeat(10)));
+        }
+        input.push_str("]><note><body>&lol9;</body></note>");
+
+        let err = from_str::<Note>(&input).unwrap_err();
+        assert_eq!(Some(Limit::EntityExpansion), err.limit());
+
+        let recursive = r#"<!DOCTYPE note [<!ENTITY a "&b;"><!ENTITY b "&a;">]><note><body>&a;</body></note>"#;
+        assert!(from_str::<Note>(recursive).is_err());
+
+        let reader = DecodingReader::new(input.as_bytes());
+        let limits = Limits { max_entity_expansion: 100, ..Limits::unlimited() };
+        let mut de = Deserializer::new_decoding(reader).unwrap().with_limits(limits);
+        let err = Note::deserialize(&mut de).unwrap_err();
+        assert_eq!("entity expansion limit of 100 exceeded", err.to_string());
+    }
+}
+
+mod lenient {
+    use super::*;
+    use std::io::{self, Read};
+    use super::super::{from_reader_lenient, from_str_lenient, Diagnostic};
+    use crate::{DecodingReader, Deserializer, Limit, Limits};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Record {
+        #[serde(rename = "@id")]
+        id: u32,
+        #[serde(rename = "@rank")]
+        rank: Option<u8>,
+        name: String,
+        age: Option<u32>,
+        #[serde(default)]
+        active: bool,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Import {
+        record: Vec<Record>,
+    }
+
+    fn record(id: u32, rank: Option<u8>, name: &str, age: Option<u32>, active: bool) -> Record {
+        Record { id, rank, name: name.to_string(), age, active }
+    }
+
+    #[test]
+    fn valid() {
+        setup();
+
+        let input = r#"<import><record id="1"><name>Ann</name></record><record id="2" rank="1"><name>Bob</name></record></import>"#;
+        let (import, diagnostics) = from_str_lenient::<Import>(input).unwrap();
+
+        assert_eq!(vec![record(1, None, "Ann", None, false), record(2, Some(1), "Bob", None, false)], import.record);
+        assert!(diagnostics.is_empty());
+        assert_eq!(import, from_str(input).unwrap());
+    }
+
+    #[test]
+    fn optional_and_default_fields() {
+        setup();
+
+        let input = indoc!(r#"
+            <import>
+              <record id="1" rank="high"><name>Ann</name><age>forty</age><active>yes</active></record>
+              <record id="2" rank="3"><name>Bob</name><age>30</age><active>true</active></record>
+            </import>"#);
+
+        assert!(from_str::<Import>(input).is_err());
+
+        let (import, diagnostics) = from_str_lenient::<Import>(input).unwrap();
+        assert_eq!(vec![record(1, None, "Ann", None, false), record(2, Some(3), "Bob", Some(30), true)], import.record);
+
+        let names: Vec<_> = diagnostics.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(vec!["@rank", "age", "active"], names);
+        assert_eq!(
+            Diagnostic { line: 2, column: 3, name: "@rank".to_string(), message: "invalid digit found in string".to_string() },
+            diagnostics[0]);
+        assert_eq!("2:46: age: invalid digit found in string", diagnostics[1].to_string());
+        assert_eq!("provided string was not `true` or `false`", diagnostics[2].message);
+    }
+
+    #[test]
+    fn required_field() {
+        setup();
+
+        let input = r#"<import><record id="one"><name>Ann</name></record></import>"#;
+        assert_eq!("invalid digit found in string", from_str_lenient::<Import>(input).unwrap_err().to_string());
+
+        let input = r#"<import><record id="1"></record></import>"#;
+        assert_eq!("missing field `name`", from_str_lenient::<Import>(input).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn read_once() {
+        setup();
+
+        struct Counting<'a>(&'a [u8], usize);
+
+        impl<'a> Read for Counting<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = self.0.read(buf)?;
+                self.1 += n;
+                Ok(n)
+            }
+        }
+
+        let mut input = "<import>".to_string();
+        for id in 0..100 {
+            input.push_str(&format!("<record id=\"{0}\" rank=\"r{0}\"><name>N</name><age>a{0}</age></record>", id));
+        }
+        input.push_str("</import>");
+
+        let mut reader = Counting(input.as_bytes(), 0);
+        let (import, diagnostics) = from_reader_lenient::<_, Import>(&mut reader).unwrap();
+        assert_eq!(100, import.record.len());
+        assert_eq!(200, diagnostics.len());
+        assert_eq!(input.len(), reader.1);
+    }
+
+    #[test]
+    fn limits() {
+        setup();
+
+        let input = format!("<import>{}</import>", "<record id=\"1\"><name>Ann</name></record>".repeat(10));
+        let limits = Limits { max_input_bytes: 64, ..Limits::default() };
+        let de = Deserializer::new_decoding(DecodingReader::new(input.as_bytes())).unwrap().with_limits(limits);
+
+        let err = de.deserialize_lenient::<Import>().unwrap_err();
+        assert_eq!(Some(Limit::InputBytes), err.limit());
+    }
+
+    #[test]
+    fn unknown_elements() {
+        setup();
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Strict {
+            name: String,
+            #[serde(rename = "@id")]
+            id: Option<u32>,
+        }
+
+        let input = r#"<strict id="1" lang="en"><name>Ann</name><nickname>A</nickname></strict>"#;
+        let err = from_str::<Strict>(input).unwrap_err();
+        assert_eq!("unknown field `@lang`, expected `name` or `@id`", err.to_string());
+
+        let (strict, diagnostics) = from_reader_lenient::<_, Strict>(input.as_bytes()).unwrap();
+        assert_eq!(Strict { name: "Ann".to_string(), id: Some(1) }, strict);
+        assert_eq!(
+            vec!["1:25: @lang: unknown field `@lang`, expected `name` or `@id`",
+                 "1:42: nickname: unknown field `nickname`, expected `name` or `@id`"],
+            diagnostics.iter().map(Diagnostic::to_string).collect::<Vec<_>>());
+
+        let input = r#"<import><record id="1" lang="en"><name>Ann</name><bogus/></record></import>"#;
+        let (import, diagnostics) = from_str_lenient::<Import>(input).unwrap();
+        assert_eq!(vec![record(1, None, "Ann", None, false)], import.record);
+        assert_eq!(
+            vec!["1:9: @lang: unknown field `@lang`, expected one of `@id`, `@rank`, `name`, `age`, `active`",
+                 "1:50: bogus: unknown field `bogus`, expected one of `@id`, `@rank`, `name`, `age`, `active`"],
+            diagnostics.iter().map(Diagnostic::to_string).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn records_read_again() {
+        setup();
+
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use crate::List;
+
+        static READS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Entry {
+            #[serde(rename = "@tags")]
+            tags: Option<List<u32>>,
+            name: String,
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct Counted(Entry);
+
+        impl<'de> serde::Deserialize<'de> for Counted {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                READS.fetch_add(1, Ordering::SeqCst);
+                <Entry as serde::Deserialize>::deserialize(deserializer).map(Counted)
+            }
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Batch {
+            entry: Vec<Counted>,
+        }
+
+        // Lists are not read as their default in place, so each entry has
+        // to be read again
+        let mut input = "<batch>".to_string();
+        for id in 0..100 {
+            input.push_str(&format!("<entry tags=\"1 t{}\"><name>N</name></entry>", id));
+        }
+        input.push_str("</batch>");
+
+        let (batch, diagnostics) = from_str_lenient::<Batch>(&input).unwrap();
+        assert_eq!(100, batch.entry.len());
+        assert_eq!(Counted(Entry { tags: None, name: "N".to_string() }), batch.entry[99]);
+        assert_eq!(100, diagnostics.len());
+        assert_eq!(200, READS.load(Ordering::SeqCst));
+    }
 }
 
 mod any {
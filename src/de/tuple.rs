@@ -1,19 +1,34 @@
 use std::io::Read;
 
+use serde::de::IntoDeserializer;
+
+use xml::reader::XmlEvent;
+
 use super::Deserializer;
-use crate::error::{self, Error, Result};
+use super::plain::single_char;
+use crate::lexical::ValueFormat;
+use crate::error::{Error, Result};
 
 pub struct TupleAccess {
     items: ::std::vec::IntoIter<String>,
+    format: ValueFormat,
 }
 
 impl TupleAccess {
     pub fn new<'a, R: 'a + Read>(de: &'a mut Deserializer<R>) -> Result<Self> {
         trace!("looking for tuple");
-        let items: Vec<String> = de.characters()?.split_whitespace()
+        let format = de.format;
+        if let XmlEvent::EndElement { .. } = *de.peek()? {
+            return Ok(Self::from_characters("", format));
+        }
+        Ok(Self::from_characters(&de.characters()?, format))
+    }
+
+    pub fn from_characters(s: &str, format: ValueFormat) -> Self {
+        let items: Vec<String> = s.split_whitespace()
             .map(String::from)
             .collect();
-        Ok(TupleAccess { items: items.into_iter() })
+        TupleAccess { items: items.into_iter(), format }
     }
 }
 
@@ -29,18 +44,18 @@ impl<'de> serde::de::SeqAccess<'de> for TupleAccess {
             None => Ok(None),
             Some(item) => {
                 trace!("found tuple item {}", item);
-                seed.deserialize(TupleValueDeserializer(item)).map(Some)
+                seed.deserialize(TupleValueDeserializer(item, self.format)).map(Some)
             },
         }
     }
 }
 
-struct TupleValueDeserializer(String);
+struct TupleValueDeserializer(String, ValueFormat);
 
 macro_rules! deserialize_type_tuple {
-    ($deserialize:ident, $error:expr => $visit:ident) => {
+    ($deserialize:ident, $parse:ident => $visit:ident) => {
         fn $deserialize<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-            visitor.$visit(self.0.parse().map_err($error)?)
+            visitor.$visit(self.1.$parse(&self.0)?)
         }
     }
 }
@@ -52,19 +67,55 @@ impl<'de> serde::de::Deserializer<'de> for TupleValueDeserializer {
         visitor.visit_string(self.0)
     }
 
-    deserialize_type_tuple!(deserialize_i8, error::parse_int => visit_i8);
-    deserialize_type_tuple!(deserialize_i16, error::parse_int => visit_i16);
-    deserialize_type_tuple!(deserialize_i32, error::parse_int => visit_i32);
-    deserialize_type_tuple!(deserialize_i64, error::parse_int => visit_i64);
-    deserialize_type_tuple!(deserialize_u8, error::parse_int => visit_u8);
-    deserialize_type_tuple!(deserialize_u16, error::parse_int => visit_u16);
-    deserialize_type_tuple!(deserialize_u32, error::parse_int => visit_u32);
-    deserialize_type_tuple!(deserialize_u64, error::parse_int => visit_u64);
-    deserialize_type_tuple!(deserialize_f32, error::parse_float => visit_f32);
-    deserialize_type_tuple!(deserialize_f64, error::parse_float => visit_f64);
+    deserialize_type_tuple!(deserialize_bool, parse_bool => visit_bool);
+
+    deserialize_type_tuple!(deserialize_i8, parse_int => visit_i8);
+    deserialize_type_tuple!(deserialize_i16, parse_int => visit_i16);
+    deserialize_type_tuple!(deserialize_i32, parse_int => visit_i32);
+    deserialize_type_tuple!(deserialize_i64, parse_int => visit_i64);
+    deserialize_type_tuple!(deserialize_u8, parse_int => visit_u8);
+    deserialize_type_tuple!(deserialize_u16, parse_int => visit_u16);
+    deserialize_type_tuple!(deserialize_u32, parse_int => visit_u32);
+    deserialize_type_tuple!(deserialize_u64, parse_int => visit_u64);
+    deserialize_type_tuple!(deserialize_f32, parse_float => visit_f32);
+    deserialize_type_tuple!(deserialize_f64, parse_float => visit_f64);
+
+    fn deserialize_char<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_char(single_char(&self.0)?)
+    }
+
+    fn deserialize_unit<V: serde::de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let variant: serde::de::value::StringDeserializer<Error> = self.0.into_deserializer();
+        visitor.visit_enum(variant)
+    }
 
     forward_to_deserialize_any! {
-        char str string unit seq bytes map unit_struct newtype_struct tuple_struct
-        struct identifier tuple ignored_any byte_buf enum option bool
+        str string seq bytes map tuple_struct
+        struct identifier tuple ignored_any byte_buf option
     }
 }
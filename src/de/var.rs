@@ -6,6 +6,7 @@ use serde::de::{
 };
 
 use xml::name::OwnedName;
+use xml::reader::XmlEvent;
 
 use super::{Deserializer, qualified_name_from};
 use crate::error::{self, Error, Result};
@@ -41,9 +42,12 @@ impl<'de, 'a, R: 'a + Read> serde::de::EnumAccess<'de> for VariantAccess<'a, R>
 impl<'de, 'a, R: 'a + Read> serde::de::VariantAccess<'de> for VariantAccess<'a, R> {
     type Error = Error;
 
+    /// An empty element, as text is handled by `UnitVariantAccess`.
     fn unit_variant(self) -> Result<()> {
-        // Handled by UnitVariantAccess
-        unimplemented!()
+        match *self.de.peek()? {
+            XmlEvent::EndElement { .. } => Ok(()),
+            _ => Err(error::with_message("expected unit variant".to_string())),
+        }
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
@@ -74,7 +78,7 @@ pub struct UnitVariantAccess<'a, R: 'a + Read> {
 
 impl<'a, R: 'a + Read> UnitVariantAccess<'a, R> {
     pub fn new(de: &'a mut Deserializer<R>) -> Self {
-        UnitVariantAccess { de: de }
+        UnitVariantAccess { de }
     }
 }
 
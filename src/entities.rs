@@ -0,0 +1,77 @@
+//! Entities declared in the document type declaration.
+//!
+//! Entities declared in the internal subset, such as
+//! `<!ENTITY product "Widget">`, are expanded when reading through a
+//! `DecodingReader`, as `from_str` and `from_reader` do, and named entities
+//! such as `&nbsp;` can be added to those a document declares.
+//!
+//! External entities, whose replacement text is to be read from their system
+//! identifier, are refused unless an `EntityResolver` is given, so that a
+//! document cannot make the reader open local files or URLs on its behalf.
+//! With a resolver, an external DTD subset also has its entity declarations
+//! read.
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate serde;
+//! # extern crate serde_xml;
+//! # use serde::Deserialize;
+//! # use serde_xml::{DecodingReader, Deserializer};
+//! #[derive(Debug, PartialEq, Deserialize)]
+//! struct Note {
+//!     body: String,
+//! }
+//!
+//! # fn main() {
+//! let input = r#"<!DOCTYPE note [<!ENTITY product "Widget">]>
+//!     <note><body>&product;&nbsp;&copy;</body></note>"#;
+//! let reader = DecodingReader::new(input.as_bytes())
+//!     .with_entities(vec![("nbsp", "\u{A0}"), ("copy", "\u{A9}")]);
+//! let note = Note::deserialize(&mut Deserializer::new_decoding(reader).unwrap()).unwrap();
+//! assert_eq!("Widget\u{A0}\u{A9}", note.body);
+//! # }
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Provides the replacement text of external entities and external DTD
+/// subsets.
+pub trait EntityResolver {
+    /// Text of the entity declared with `public_id` and `system_id`, or an
+    /// error to refuse it.
+    fn resolve(&self, public_id: Option<&str>, system_id: &str) -> io::Result<String>;
+}
+
+impl<F: Fn(Option<&str>, &str) -> io::Result<String>> EntityResolver for F {
+    fn resolve(&self, public_id: Option<&str>, system_id: &str) -> io::Result<String> {
+        self(public_id, system_id)
+    }
+}
+
+/// Reads external entities from files under a directory, refusing system
+/// identifiers that are URLs, absolute paths or lead out of it.
+#[derive(Debug, Clone)]
+pub struct DirectoryResolver {
+    root: PathBuf,
+}
+
+impl DirectoryResolver {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirectoryResolver { root: root.into() }
+    }
+}
+
+impl EntityResolver for DirectoryResolver {
+    fn resolve(&self, _public_id: Option<&str>, system_id: &str) -> io::Result<String> {
+        let path = Path::new(system_id);
+        let inside = !system_id.contains(':') && !system_id.contains('\\')
+            && path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !inside {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!(
+                "system identifier {:?} is outside of {}", system_id, self.root.display())));
+        }
+        fs::read_to_string(self.root.join(path))
+    }
+}
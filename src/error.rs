@@ -6,9 +6,13 @@ use std::str::ParseBoolError;
 use std::string::FromUtf8Error;
 use std::result;
 
+use base64::DecodeError;
+
 use xml::reader;
 use xml::writer;
 
+use super::limits::Limit;
+
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -25,6 +29,10 @@ pub enum ErrorImpl {
     ParseFloatError(ParseFloatError),
     ParseBoolError(ParseBoolError),
     FromUtf8Error(FromUtf8Error),
+    Base64(DecodeError),
+    LimitExceeded(Limit, usize),
+    UnknownField(String, &'static [&'static str]),
+    MissingField(&'static str),
 }
 
 pub fn with_message(s: String) -> Error {
@@ -44,6 +52,10 @@ pub fn parse_bool(err: ParseBoolError) -> Error {
 }
 
 pub fn reader(err: reader::Error) -> Error {
+    // The parser would describe it by its deprecated `description`
+    if let reader::ErrorKind::Io(ref err) = *err.kind() {
+        return io(io::Error::new(err.kind(), err.to_string()));
+    }
     Error(Box::new(ErrorImpl::Reader(err)))
 }
 
@@ -59,6 +71,37 @@ pub fn from_utf8(err: FromUtf8Error) -> Error {
     Error(Box::new(ErrorImpl::FromUtf8Error(err)))
 }
 
+pub fn base64(err: DecodeError) -> Error {
+    Error(Box::new(ErrorImpl::Base64(err)))
+}
+
+pub fn limit_exceeded(limit: Limit, max: usize) -> Error {
+    Error(Box::new(ErrorImpl::LimitExceeded(limit, max)))
+}
+
+impl Error {
+    /// The limit the document went over, if that is why it was rejected.
+    pub fn limit(&self) -> Option<Limit> {
+        match *self.0 {
+            ErrorImpl::LimitExceeded(limit, _) => Some(limit),
+            _ => None,
+        }
+    }
+
+    /// Whether a lenient read may skip what the error is about.
+    pub(crate) fn is_recoverable(&self) -> bool {
+        matches!(*self.0, ErrorImpl::ParseIntError(_) | ErrorImpl::ParseFloatError(_)
+            | ErrorImpl::ParseBoolError(_) | ErrorImpl::UnknownField(..))
+    }
+
+    pub(crate) fn missing_field(&self) -> Option<&str> {
+        match *self.0 {
+            ErrorImpl::MissingField(field) => Some(field),
+            _ => None,
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.0 {
@@ -70,6 +113,21 @@ impl Display for Error {
             ErrorImpl::ParseFloatError(ref err) => write!(f, "{}", err),
             ErrorImpl::ParseBoolError(ref err) => write!(f, "{}", err),
             ErrorImpl::FromUtf8Error(ref err) => write!(f, "{}", err),
+            ErrorImpl::Base64(ref err) => write!(f, "{}", err),
+            ErrorImpl::LimitExceeded(limit, max) => write!(f, "{} limit of {} exceeded", limit, max),
+            ErrorImpl::UnknownField(ref field, expected) => {
+                write!(f, "unknown field `{}`, ", field)?;
+                match *expected {
+                    [] => write!(f, "there are no fields"),
+                    [only] => write!(f, "expected `{}`", only),
+                    [first, second] => write!(f, "expected `{}` or `{}`", first, second),
+                    _ => {
+                        let expected: Vec<_> = expected.iter().map(|field| format!("`{}`", field)).collect();
+                        write!(f, "expected one of {}", expected.join(", "))
+                    },
+                }
+            },
+            ErrorImpl::MissingField(field) => write!(f, "missing field `{}`", field),
         }
     }
 }
@@ -82,6 +140,14 @@ impl serde::de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error(Box::new(ErrorImpl::Message(msg.to_string())))
     }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        Error(Box::new(ErrorImpl::UnknownField(field.to_string(), expected)))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Error(Box::new(ErrorImpl::MissingField(field)))
+    }
 }
 
 impl serde::ser::Error for Error {
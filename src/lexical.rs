@@ -0,0 +1,89 @@
+use std::num::{ParseFloatError, ParseIntError};
+use std::str::FromStr;
+
+use super::binary::BinaryEncoding;
+use super::error::{self, Result};
+
+/// Lexical forms used for booleans and numbers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LexicalForm {
+    /// XML Schema lexical forms: `1`/`0` are booleans, `INF`, `-INF` and
+    /// `NaN` are floats, and surrounding whitespace is ignored.
+    #[default]
+    Xsd,
+    /// Only what Rust's `FromStr` and `Display` implementations accept and
+    /// produce.
+    Rust,
+}
+
+/// How scalar values are read from and written to character data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ValueFormat {
+    pub lexical_form: LexicalForm,
+    pub binary_encoding: BinaryEncoding,
+}
+
+impl ValueFormat {
+    pub fn parse_bool(self, s: &str) -> Result<bool> {
+        match self.lexical_form {
+            LexicalForm::Xsd => match s.trim() {
+                "true" | "1" => Ok(true),
+                "false" | "0" => Ok(false),
+                other => other.parse().map_err(error::parse_bool),
+            },
+            LexicalForm::Rust => s.parse().map_err(error::parse_bool),
+        }
+    }
+
+    pub fn parse_int<T>(self, s: &str) -> Result<T>
+    where
+        T: FromStr<Err = ParseIntError>,
+    {
+        match self.lexical_form {
+            LexicalForm::Xsd => s.trim().parse().map_err(error::parse_int),
+            LexicalForm::Rust => s.parse().map_err(error::parse_int),
+        }
+    }
+
+    pub fn parse_float<T>(self, s: &str) -> Result<T>
+    where
+        T: FromStr<Err = ParseFloatError>,
+    {
+        match self.lexical_form {
+            LexicalForm::Xsd => {
+                let s = s.trim();
+                let literal = match s {
+                    // `+INF` is only valid as of XML Schema 1.1
+                    "INF" => "inf",
+                    "-INF" => "-inf",
+                    "NaN" => "NaN",
+                    // Rust also accepts spellings such as `inf` or `infinity`
+                    // which are not valid XML Schema doubles.
+                    _ if s.bytes().any(|b| b.is_ascii_alphabetic() && b != b'e' && b != b'E') => "invalid",
+                    _ => s,
+                };
+                literal.parse().map_err(error::parse_float)
+            },
+            LexicalForm::Rust => s.parse().map_err(error::parse_float),
+        }
+    }
+
+    pub fn format_f64(self, v: f64) -> String {
+        match self.lexical_form {
+            LexicalForm::Xsd if v.is_nan() => "NaN".to_string(),
+            LexicalForm::Xsd if v == f64::INFINITY => "INF".to_string(),
+            LexicalForm::Xsd if v == f64::NEG_INFINITY => "-INF".to_string(),
+            _ => v.to_string(),
+        }
+    }
+
+    pub fn format_f32(self, v: f32) -> String {
+        if v.is_finite() {
+            // Widening first would print the binary approximation, e.g.
+            // 1.100000023841858 instead of 1.1
+            v.to_string()
+        } else {
+            self.format_f64(f64::from(v))
+        }
+    }
+}
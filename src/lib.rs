@@ -4,6 +4,12 @@ extern crate log;
 extern crate serde;
 
 extern crate xml;
+extern crate base64;
+extern crate encoding_rs;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
 
 #[cfg(test)]
 #[macro_use]
@@ -16,13 +22,46 @@ extern crate indoc;
 #[cfg(test)]
 extern crate env_logger;
 
+#[cfg(test)]
+extern crate serde_bytes;
+
+#[cfg(feature = "tokio")]
+pub mod async_io;
+mod binary;
+pub mod c14n;
+pub mod cdata;
 mod de;
+pub mod entities;
 mod error;
+mod lexical;
+mod limits;
+pub mod list;
+mod maps;
+mod name;
+mod prolog;
 mod ser;
 
-pub use de::{from_str, from_reader, Deserializer};
+#[cfg(feature = "tokio")]
+pub use async_io::{from_async_reader, to_async_writer, RecordStream};
+pub use binary::BinaryEncoding;
+pub use cdata::CData;
+pub use de::{
+    from_str, from_str_lenient, from_str_with_prolog, from_reader, from_reader_lenient,
+    from_reader_with_encoding, from_reader_with_prolog, DecodingReader, Deserializer, Diagnostic,
+    StanzaReader, StreamDeserializer, StreamHeader,
+};
+pub use entities::EntityResolver;
 pub use error::{Error, Result};
-pub use ser::{to_string, to_string_ns, to_writer, Serializer};
+pub use lexical::LexicalForm;
+pub use limits::{Limit, Limits};
+pub use list::List;
+pub use maps::MapRepresentation;
+pub use prolog::{Prolog, ProcessingInstruction};
+pub use ser::{
+    to_string, to_string_compact, to_string_ns, to_string_pretty, to_vec, to_writer,
+    AttributeOrder, IllegalCharacters, Serializer, SerializerBuilder, Transcoder, XmlVersion,
+    XmlWriter,
+};
 
 #[cfg(test)]
 mod tests;
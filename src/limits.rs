@@ -0,0 +1,83 @@
+use std::fmt::{self, Display};
+
+/// Bounds on what a document may contain, so that a hostile one cannot
+/// exhaust the stack or memory. Going over one fails deserialization with an
+/// error whose `limit` says which.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Nesting depth of elements, the root element being at depth 1.
+    pub max_depth: usize,
+    /// Attributes on a single element.
+    pub max_attributes: usize,
+    /// Bytes in a text node or attribute value.
+    pub max_text_length: usize,
+    /// Elements in the whole document.
+    pub max_elements: usize,
+    /// Bytes read from the input, or buffered for a single record by the
+    /// readers of streams.
+    pub max_input_bytes: usize,
+    /// Bytes entity references expand to in the whole document.
+    pub max_entity_expansion: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 128,
+            max_attributes: 1024,
+            max_text_length: 16 * 1024 * 1024,
+            max_elements: 1_000_000,
+            max_input_bytes: 64 * 1024 * 1024,
+            max_entity_expansion: 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits, for trusted input.
+    pub fn unlimited() -> Self {
+        Limits {
+            max_depth: usize::MAX,
+            max_attributes: usize::MAX,
+            max_text_length: usize::MAX,
+            max_elements: usize::MAX,
+            max_input_bytes: usize::MAX,
+            max_entity_expansion: usize::MAX,
+        }
+    }
+
+    pub(crate) fn max(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::Depth => self.max_depth,
+            Limit::Attributes => self.max_attributes,
+            Limit::TextLength => self.max_text_length,
+            Limit::Elements => self.max_elements,
+            Limit::InputBytes => self.max_input_bytes,
+            Limit::EntityExpansion => self.max_entity_expansion,
+        }
+    }
+}
+
+/// The limit a document went over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    Attributes,
+    TextLength,
+    Elements,
+    InputBytes,
+    EntityExpansion,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Limit::Depth => "element depth",
+            Limit::Attributes => "attributes per element",
+            Limit::TextLength => "text length",
+            Limit::Elements => "element count",
+            Limit::InputBytes => "input size",
+            Limit::EntityExpansion => "entity expansion",
+        })
+    }
+}
@@ -0,0 +1,124 @@
+//! Whitespace-separated lists, as used by XML Schema `xs:list` types.
+//!
+//! Sequences are normally written as repeated elements. Wrapping a field in
+//! `List`, or pointing `serialize_with`/`deserialize_with` at this module,
+//! writes the items as whitespace-separated tokens instead, either in an
+//! attribute or in the text of an element. Items that are empty or contain
+//! whitespace would not read back, so writing them fails:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate serde_xml;
+//! #[derive(Serialize, Deserialize)]
+//! struct Shape {
+//!     #[serde(rename = "@coords", with = "serde_xml::list")]
+//!     coords: Vec<f64>,
+//!     tags: serde_xml::List<String>,
+//! }
+//! # fn main() {}
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+use error::{self, Result as XmlResult};
+
+/// Newtype name the serializer recognizes to check list items, other formats
+/// see the inner value.
+pub(crate) const ITEM: &str = "$serde_xml::ListItem";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct List<T>(pub Vec<T>);
+
+impl<T> From<Vec<T>> for List<T> {
+    fn from(items: Vec<T>) -> Self {
+        List(items)
+    }
+}
+
+impl<T> Deref for List<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for List<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<T: Serialize> Serialize for List<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for List<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(List)
+    }
+}
+
+pub fn serialize<S, T>(items: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let mut tuple = serializer.serialize_tuple(items.len())?;
+    for item in items {
+        tuple.serialize_element(&Item(item))?;
+    }
+    tuple.end()
+}
+
+struct Item<'a, T: 'a>(&'a T);
+
+impl<'a, T: Serialize> Serialize for Item<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(ITEM, self.0)
+    }
+}
+
+/// Fails for an item that would not read back as a single token.
+pub(crate) fn check_item(item: &str) -> XmlResult<()> {
+    if item.is_empty() {
+        Err(error::with_message("empty list item".into()))
+    } else if item.contains(char::is_whitespace) {
+        Err(error::with_message(format!("list item {:?} contains whitespace", item)))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    deserializer.deserialize_tuple(usize::MAX, ListVisitor(PhantomData))
+}
+
+struct ListVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a whitespace-separated list")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,18 @@
+/// How maps are written as XML.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum MapRepresentation {
+    /// One element per entry, named after the key, which must then be a valid
+    /// XML name: `<key>value</key>`.
+    #[default]
+    Elements,
+    /// One element per entry, with the key in an attribute:
+    /// `<entry key="key">value</entry>`.
+    Entries { element: String, key: String },
+}
+
+impl MapRepresentation {
+    /// Entries written as `<entry key="...">`.
+    pub fn entries() -> Self {
+        MapRepresentation::Entries { element: "entry".to_string(), key: "key".to_string() }
+    }
+}
@@ -0,0 +1,41 @@
+//! Everything that comes before the root element of a document.
+
+use ser::XmlVersion;
+
+/// The XML declaration, processing instructions and document type
+/// declaration preceding the root element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prolog {
+    pub version: XmlVersion,
+    pub encoding: String,
+    pub standalone: Option<bool>,
+    pub processing_instructions: Vec<ProcessingInstruction>,
+    /// Contents of `<!DOCTYPE ...>`, without the keyword, for example
+    /// `html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "xhtml1-strict.dtd"`.
+    pub doctype: Option<String>,
+}
+
+impl Default for Prolog {
+    fn default() -> Self {
+        Prolog {
+            version: XmlVersion::default(),
+            encoding: "UTF-8".to_string(),
+            standalone: None,
+            processing_instructions: Vec::new(),
+            doctype: None,
+        }
+    }
+}
+
+/// A processing instruction such as `<?OFX VERSION="203"?>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessingInstruction {
+    pub target: String,
+    pub data: Option<String>,
+}
+
+impl ProcessingInstruction {
+    pub fn new<S: Into<String>>(target: S, data: Option<&str>) -> Self {
+        ProcessingInstruction { target: target.into(), data: data.map(|s| s.to_string()) }
+    }
+}
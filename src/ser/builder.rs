@@ -0,0 +1,386 @@
+use std::io::Write;
+
+use serde::ser::Serialize;
+
+use xml::common;
+use xml::writer::EmitterConfig;
+
+use super::encoding::{OutputEncoding, Transcoder};
+use super::error::{self, Result};
+use super::{is_valid_comment, is_valid_doctype, AttributeOrder, IllegalCharacters, Serializer, XmlWriter};
+use super::super::binary::BinaryEncoding;
+use super::super::c14n::{self, Canonicalization};
+use super::super::lexical::{LexicalForm, ValueFormat};
+use super::super::maps::MapRepresentation;
+use super::super::prolog::{Prolog, ProcessingInstruction};
+
+/// Version written in the XML declaration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum XmlVersion {
+    #[default]
+    Version10,
+    Version11,
+}
+
+impl From<XmlVersion> for common::XmlVersion {
+    fn from(version: XmlVersion) -> Self {
+        match version {
+            XmlVersion::Version10 => common::XmlVersion::Version10,
+            XmlVersion::Version11 => common::XmlVersion::Version11,
+        }
+    }
+}
+
+impl From<common::XmlVersion> for XmlVersion {
+    fn from(version: common::XmlVersion) -> Self {
+        match version {
+            common::XmlVersion::Version10 => XmlVersion::Version10,
+            common::XmlVersion::Version11 => XmlVersion::Version11,
+        }
+    }
+}
+
+/// Values written in the XML declaration when they differ from what the
+/// writer would produce on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub version: XmlVersion,
+    pub encoding: Option<String>,
+    pub standalone: Option<bool>,
+}
+
+/// Markup written between the XML declaration and the root element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrologItem {
+    ProcessingInstruction(ProcessingInstruction),
+    Comment(String),
+    Doctype(String),
+}
+
+/// Configures how values are written as XML.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # extern crate serde_xml;
+/// # use serde_xml::SerializerBuilder;
+/// #[derive(Serialize)]
+/// #[serde(rename = "document")]
+/// struct Document {
+///     content: String,
+/// }
+///
+/// # fn main() {
+/// let document = Document { content: "abc".to_string() };
+/// let xml = SerializerBuilder::compact()
+///     .xml_declaration(false)
+///     .to_string(&document)
+///     .unwrap();
+/// assert_eq!("<document><content>abc</content></document>", xml);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SerializerBuilder {
+    indent: bool,
+    indent_string: String,
+    line_separator: String,
+    self_closing: bool,
+    xml_declaration: bool,
+    xml_version: XmlVersion,
+    encoding: Option<String>,
+    standalone: Option<bool>,
+    prolog: Vec<PrologItem>,
+    default_ns: Option<String>,
+    namespaces: Vec<(String, String)>,
+    format: ValueFormat,
+    attribute_order: AttributeOrder,
+    map_representation: MapRepresentation,
+    illegal_characters: IllegalCharacters,
+    canonical: Option<Canonicalization>,
+}
+
+impl Default for SerializerBuilder {
+    fn default() -> Self {
+        SerializerBuilder {
+            indent: true,
+            indent_string: "  ".to_string(),
+            line_separator: "\n".to_string(),
+            self_closing: true,
+            xml_declaration: true,
+            xml_version: XmlVersion::default(),
+            encoding: None,
+            standalone: None,
+            prolog: Vec::new(),
+            default_ns: None,
+            namespaces: Vec::new(),
+            format: ValueFormat::default(),
+            attribute_order: AttributeOrder::default(),
+            map_representation: MapRepresentation::default(),
+            illegal_characters: IllegalCharacters::default(),
+            canonical: None,
+        }
+    }
+}
+
+impl SerializerBuilder {
+    /// Indented output, as written by `to_string`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Output without any whitespace between elements.
+    pub fn compact() -> Self {
+        Self::default().indent(false)
+    }
+
+    pub fn indent(mut self, indent: bool) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    pub fn indent_string<S: Into<String>>(mut self, indent_string: S) -> Self {
+        self.indent_string = indent_string.into();
+        self
+    }
+
+    pub fn line_separator<S: Into<String>>(mut self, line_separator: S) -> Self {
+        self.line_separator = line_separator.into();
+        self
+    }
+
+    /// Whether empty elements are written as `<a />` rather than `<a></a>`.
+    pub fn self_closing(mut self, self_closing: bool) -> Self {
+        self.self_closing = self_closing;
+        self
+    }
+
+    pub fn xml_declaration(mut self, xml_declaration: bool) -> Self {
+        self.xml_declaration = xml_declaration;
+        self
+    }
+
+    pub fn xml_version(mut self, xml_version: XmlVersion) -> Self {
+        self.xml_version = xml_version;
+        self
+    }
+
+    /// Encoding declared in the XML declaration and used for the output.
+    /// Characters the encoding cannot represent are written as character
+    /// references.
+    pub fn encoding<S: Into<String>>(mut self, encoding: S) -> Self {
+        self.encoding = Some(encoding.into());
+        self
+    }
+
+    pub fn standalone(mut self, standalone: bool) -> Self {
+        self.standalone = Some(standalone);
+        self
+    }
+
+    /// Writes `<?target data?>` before the root element.
+    pub fn processing_instruction<S: Into<String>>(mut self, target: S, data: Option<&str>) -> Self {
+        self.prolog.push(PrologItem::ProcessingInstruction(ProcessingInstruction::new(target, data)));
+        self
+    }
+
+    /// Writes `<!-- comment -->` before the root element.
+    pub fn comment<S: Into<String>>(mut self, comment: S) -> Self {
+        self.prolog.push(PrologItem::Comment(comment.into()));
+        self
+    }
+
+    /// Writes `<!DOCTYPE doctype>` before the root element, replacing any
+    /// previous one. Serializing fails if `doctype` does not start with a
+    /// name or would end the declaration early.
+    pub fn doctype<S: Into<String>>(mut self, doctype: S) -> Self {
+        let doctype = PrologItem::Doctype(doctype.into());
+        match self.prolog.iter().position(|item| matches!(item, PrologItem::Doctype(_))) {
+            Some(index) => self.prolog[index] = doctype,
+            None => self.prolog.push(doctype),
+        }
+        self
+    }
+
+    /// Writes the version, standalone flag, processing instructions and
+    /// document type of a prolog read by the deserializer. The output
+    /// encoding is left as configured.
+    pub fn prolog(mut self, prolog: &Prolog) -> Self {
+        self.xml_version = prolog.version;
+        self.standalone = prolog.standalone;
+        for pi in &prolog.processing_instructions {
+            self.prolog.push(PrologItem::ProcessingInstruction(pi.clone()));
+        }
+        match prolog.doctype {
+            Some(ref doctype) => self.doctype(doctype.as_str()),
+            None => self,
+        }
+    }
+
+    pub fn default_namespace<S: Into<String>>(mut self, uri: S) -> Self {
+        self.default_ns = Some(uri.into());
+        self
+    }
+
+    pub fn namespace<S1: Into<String>, S2: Into<String>>(mut self, prefix: S1, uri: S2) -> Self {
+        self.namespaces.push((prefix.into(), uri.into()));
+        self
+    }
+
+    pub fn binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    pub fn lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.format.lexical_form = lexical_form;
+        self
+    }
+
+    pub fn attribute_order(mut self, attribute_order: AttributeOrder) -> Self {
+        self.attribute_order = attribute_order;
+        self
+    }
+
+    pub fn map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.map_representation = map_representation;
+        self
+    }
+
+    pub fn illegal_characters(mut self, illegal_characters: IllegalCharacters) -> Self {
+        self.illegal_characters = illegal_characters;
+        self
+    }
+
+    /// Writes canonical XML, which overrides the formatting options and
+    /// leaves out the declaration and document type. Only `to_writer`,
+    /// `to_vec` and `to_string` support canonical output.
+    pub fn canonical(mut self, canonicalization: Canonicalization) -> Self {
+        self.canonical = Some(canonicalization);
+        self
+    }
+
+    pub fn build<W: Write>(&self, writer: W) -> Result<Serializer<Transcoder<W>>> {
+        if self.canonical.is_some() {
+            return Err(error::with_message("canonical output cannot be streamed".into()));
+        }
+        let encoding = self.output_encoding()?;
+        let writer = Transcoder::new(writer, encoding);
+        let mut config = EmitterConfig::new()
+            .perform_indent(self.indent)
+            .indent_string(self.indent_string.clone())
+            .line_separator(self.line_separator.clone())
+            .normalize_empty_elements(self.self_closing)
+            .write_document_declaration(self.xml_declaration);
+        config.perform_escaping = encoding == OutputEncoding::Utf8;
+        let writer = config.create_writer(writer);
+
+        let namespaces: Vec<(&str, &str)> = self.namespaces.iter()
+            .map(|(prefix, uri)| (prefix.as_str(), uri.as_str()))
+            .collect();
+
+        let mut ser = Serializer::new_from_writer(writer, self.default_ns.as_deref(), &namespaces);
+        ser.format = self.format;
+        ser.attribute_order = self.attribute_order;
+        ser.map_representation = self.map_representation.clone();
+        ser.illegal_characters = self.illegal_characters;
+        ser.encoding = encoding;
+        ser.declaration = self.declaration();
+        ser.prolog = self.checked_prolog()?;
+        ser.line_separator = if self.indent { Some(self.line_separator.clone()) } else { None };
+        ser.indent_string = self.indent_string.clone();
+        Ok(ser)
+    }
+
+    /// Writes the children of the root element one at a time.
+    pub fn xml_writer<W: Write>(&self, writer: W) -> Result<XmlWriter<Transcoder<W>>> {
+        Ok(XmlWriter::new(self.build(writer)?))
+    }
+
+    pub fn to_writer<W: Write, S: Serialize>(&self, writer: W, value: &S) -> Result<()> {
+        match self.canonical {
+            Some(canonicalization) => {
+                if self.output_encoding()? != OutputEncoding::Utf8 {
+                    return Err(error::with_message("canonical XML is always UTF-8".into()));
+                }
+                let plain = SerializerBuilder { canonical: None, ..self.clone() }
+                    .indent(false)
+                    .xml_declaration(false)
+                    .to_vec(value)?;
+                c14n::canonicalize_reader(&plain[..], writer, canonicalization)
+            },
+            None => self.build(writer)?.element(value),
+        }
+    }
+
+    pub fn to_vec<S: Serialize>(&self, value: &S) -> Result<Vec<u8>> {
+        let mut writer = Vec::with_capacity(128);
+        self.to_writer(&mut writer, value)?;
+        Ok(writer)
+    }
+
+    /// Only available for UTF-8 output, use `to_vec` for other encodings.
+    pub fn to_string<S: Serialize>(&self, value: &S) -> Result<String> {
+        if self.output_encoding()? != OutputEncoding::Utf8 {
+            return Err(error::with_message(
+                "cannot write a non UTF-8 document to a string".into()));
+        }
+        String::from_utf8(self.to_vec(value)?).map_err(error::from_utf8)
+    }
+
+    fn output_encoding(&self) -> Result<OutputEncoding> {
+        match self.encoding {
+            Some(ref encoding) => OutputEncoding::for_label(encoding),
+            None => Ok(OutputEncoding::Utf8),
+        }
+    }
+
+    /// The prolog with the illegal characters policy applied, checked for
+    /// markup that would break out of its items.
+    fn checked_prolog(&self) -> Result<Vec<PrologItem>> {
+        let policy = self.illegal_characters;
+        let mut prolog = Vec::with_capacity(self.prolog.len());
+        for item in &self.prolog {
+            let item = match *item {
+                PrologItem::ProcessingInstruction(ref pi) => PrologItem::ProcessingInstruction(ProcessingInstruction {
+                    target: policy.apply(pi.target.clone())?,
+                    data: pi.data.clone().map(|data| policy.apply(data)).transpose()?,
+                }),
+                PrologItem::Comment(ref comment) => PrologItem::Comment(policy.apply(comment.clone())?),
+                PrologItem::Doctype(ref doctype) => PrologItem::Doctype(policy.apply(doctype.clone())?),
+            };
+            let valid = match item {
+                PrologItem::ProcessingInstruction(ref pi) => {
+                    !pi.target.is_empty()
+                        && !pi.target.eq_ignore_ascii_case("xml")
+                        && !pi.target.contains(char::is_whitespace)
+                        && !pi.data.as_ref().is_some_and(|data| data.contains("?>"))
+                },
+                PrologItem::Comment(ref comment) => is_valid_comment(comment),
+                PrologItem::Doctype(ref doctype) => is_valid_doctype(doctype),
+            };
+            if !valid {
+                return Err(error::with_message(format!("invalid prolog item {:?}", item)));
+            }
+            prolog.push(item);
+        }
+        Ok(prolog)
+    }
+
+    fn declaration(&self) -> Option<Declaration> {
+        // Without an explicit declaration, the writer would only add one once
+        // the root element is written, after the prolog
+        let customized = self.xml_version != XmlVersion::default()
+            || self.encoding.is_some()
+            || self.standalone.is_some()
+            || !self.prolog.is_empty();
+
+        if self.xml_declaration && customized {
+            Some(Declaration {
+                version: self.xml_version,
+                encoding: self.encoding.clone(),
+                standalone: self.standalone,
+            })
+        } else {
+            None
+        }
+    }
+}
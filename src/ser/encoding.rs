@@ -0,0 +1,167 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::str;
+
+use encoding_rs::{Encoding, EncoderResult, UTF_8};
+
+use super::error::{self, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputEncoding {
+    Utf8,
+    /// Actual ISO-8859-1, which encoding_rs maps to windows-1252.
+    Latin1,
+    Other(&'static Encoding),
+}
+
+impl OutputEncoding {
+    pub fn for_label(label: &str) -> Result<Self> {
+        let trimmed = label.trim();
+        let latin1 = ["iso-8859-1", "iso8859-1", "iso_8859-1", "latin1", "l1"];
+        if latin1.iter().any(|l| trimmed.eq_ignore_ascii_case(l)) {
+            return Ok(OutputEncoding::Latin1);
+        }
+
+        match Encoding::for_label(trimmed.as_bytes()) {
+            Some(encoding) if encoding == UTF_8 => Ok(OutputEncoding::Utf8),
+            // UTF-16 and the replacement encoding have no encoder of their own
+            Some(encoding) if encoding.output_encoding() == encoding => {
+                Ok(OutputEncoding::Other(encoding))
+            },
+            _ => Err(error::with_message(format!("unsupported output encoding {}", label))),
+        }
+    }
+
+    /// Whether `c` has a representation in the encoding.
+    pub(crate) fn can_encode(self, c: char) -> bool {
+        match self {
+            OutputEncoding::Utf8 => true,
+            OutputEncoding::Latin1 => (c as u32) < 0x100,
+            OutputEncoding::Other(encoding) => {
+                let mut buf = [0; 4];
+                let mut out = [0; 16];
+                let (result, _, _) = encoding.new_encoder()
+                    .encode_from_utf8_without_replacement(c.encode_utf8(&mut buf), &mut out, true);
+                result == EncoderResult::InputEmpty
+            },
+        }
+    }
+
+    /// Writes the characters of escaped text or of an escaped attribute value
+    /// the encoding lacks as character references.
+    pub(crate) fn references(self, s: Cow<str>) -> Cow<str> {
+        if s.chars().all(|c| self.can_encode(c)) {
+            return s;
+        }
+        let mut out = String::with_capacity(s.len() + 8);
+        for c in s.chars() {
+            if self.can_encode(c) {
+                out.push(c);
+            } else {
+                out.push_str(&format!("&#{};", c as u32));
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    /// Fails on characters the encoding lacks where character references are
+    /// not recognized, as in names, CDATA sections, comments and processing
+    /// instructions.
+    pub(crate) fn check(self, s: &str, what: &str) -> Result<()> {
+        match s.chars().find(|&c| !self.can_encode(c)) {
+            None => Ok(()),
+            Some(c) => Err(error::with_message(format!(
+                "character U+{:04X} in {} {:?} cannot be written in {}", c as u32, what, s, self.name()))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            OutputEncoding::Utf8 => "UTF-8",
+            OutputEncoding::Latin1 => "ISO-8859-1",
+            OutputEncoding::Other(encoding) => encoding.name(),
+        }
+    }
+
+    fn encode(self, s: &str, out: &mut Vec<u8>) -> io::Result<()> {
+        let unmappable = |c: char| io::Error::new(io::ErrorKind::InvalidData, format!(
+            "character U+{:04X} cannot be written in {}", c as u32, self.name()));
+        match self {
+            OutputEncoding::Utf8 => out.extend_from_slice(s.as_bytes()),
+            OutputEncoding::Latin1 => {
+                for c in s.chars() {
+                    if (c as u32) < 0x100 {
+                        out.push(c as u32 as u8);
+                    } else {
+                        return Err(unmappable(c));
+                    }
+                }
+            },
+            OutputEncoding::Other(encoding) => {
+                let mut encoder = encoding.new_encoder();
+                let mut rest = s;
+                loop {
+                    let length = encoder.max_buffer_length_from_utf8_without_replacement(rest.len())
+                        .unwrap_or(rest.len() * 8) + 8;
+                    let start = out.len();
+                    out.resize(start + length, 0);
+                    let (result, read, written) = encoder
+                        .encode_from_utf8_without_replacement(rest, &mut out[start..], true);
+                    out.truncate(start + written);
+                    match result {
+                        EncoderResult::InputEmpty => break,
+                        EncoderResult::OutputFull => rest = &rest[read..],
+                        EncoderResult::Unmappable(c) => return Err(unmappable(c)),
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Transcodes the UTF-8 written by the XML emitter into the output encoding.
+/// The serializer has already replaced the characters the encoding lacks
+/// wherever character references are allowed, any other one is an error.
+pub struct Transcoder<W: Write> {
+    inner: W,
+    encoding: OutputEncoding,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Transcoder<W> {
+    pub(crate) fn new(inner: W, encoding: OutputEncoding) -> Self {
+        Transcoder { inner, encoding, pending: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Transcoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.encoding == OutputEncoding::Utf8 {
+            return self.inner.write(buf);
+        }
+
+        self.pending.extend_from_slice(buf);
+        let valid = match str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            // Keep an incomplete trailing character for the next write
+            Err(ref e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        let mut encoded = Vec::with_capacity(valid);
+        let text = str::from_utf8(&self.pending[..valid]).expect("validated above");
+        self.encoding.encode(text, &mut encoded)?;
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
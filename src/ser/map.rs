@@ -2,9 +2,11 @@ use std::io::Write;
 
 use serde::ser::Serialize;
 
-use super::error::{Result, Error};
-use super::Serializer;
-use super::plain::to_plain_string;
+use super::error::{self, Result, Error};
+use super::super::maps::MapRepresentation;
+use super::super::name;
+use super::{check_attribute_name, Event, Serializer};
+use super::plain::{to_attribute_value, to_plain_string, to_plain_strings};
 
 pub struct MapSerializer<'ser, W: 'ser + Write> {
     ser: &'ser mut Serializer<W>,
@@ -26,16 +28,27 @@ impl<'ser, W: Write> serde::ser::SerializeMap for MapSerializer<'ser, W> {
     where
         T: ?Sized + Serialize,
     {
-        self.ser.open_tag(&to_plain_string(key)?)?;
-        Ok(())
+        let key = to_plain_string(key, self.ser.format)?;
+        match self.ser.map_representation {
+            MapRepresentation::Elements => {
+                if !name::is_element_name(&key) {
+                    return Err(error::with_message(format!("map key {:?} is not a valid element name", key)));
+                }
+                self.ser.open_tag(&key)
+            },
+            MapRepresentation::Entries { ref element, key: ref attribute } => {
+                let (element, attribute) = (element.clone(), attribute.clone());
+                self.ser.open_tag(&element)?;
+                self.ser.add_attr(attribute, key)
+            },
+        }
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut *self.ser)?;
-        Ok(())
+        self.ser.element(value)
     }
 
     fn end(self) -> Result<()>
@@ -50,40 +63,123 @@ impl<'ser, W: Write> serde::ser::SerializeMap for MapSerializer<'ser, W> {
 pub struct StructSerializer<'ser, W: 'ser + Write> {
     ser: &'ser mut Serializer<W>,
     must_close_tag: bool,
+    /// Name of the element the struct is written as, if any.
+    tag_name: Option<String>,
+    /// Attributes of its start tag, until a field other than an attribute
+    /// has it written.
+    start: Option<Vec<(String, String)>>,
+    /// Whether the attribute fields have been read ahead, and are already on
+    /// the start tag.
+    read_ahead: bool,
+    /// Otherwise, where the start tag is among the events held back, once
+    /// it has been written.
+    start_event: Option<usize>,
+    /// Whether the events are held back for this struct, rather than for an
+    /// enclosing one.
+    buffering: bool,
 }
 
 impl<'ser, W: 'ser + Write> StructSerializer<'ser, W> {
-    pub fn new(ser: &'ser mut Serializer<W>, must_close_tag: bool) -> Self
+    pub fn new(ser: &'ser mut Serializer<W>, must_close_tag: bool) -> Result<Self>
     {
-        StructSerializer { ser, must_close_tag }
+        let read_ahead = ser.attributes.take();
+        let start = ser.current_tag_attrs.take();
+        let tag_name = start.as_ref().map(|_| ser.current_tag());
+        let buffering = read_ahead.is_none() && tag_name.is_some() && ser.buffer.is_none();
+        if buffering {
+            ser.buffer = Some(Vec::new());
+        }
+        let mut serializer = StructSerializer {
+            ser,
+            must_close_tag,
+            tag_name,
+            start,
+            read_ahead: read_ahead.is_some(),
+            start_event: None,
+            buffering,
+        };
+        for (name, value) in read_ahead.unwrap_or_default() {
+            serializer.add_attr(&name, value)?;
+        }
+        Ok(serializer)
+    }
+
+    fn add_attr(&mut self, name: &str, value: String) -> Result<()> {
+        let written = match (self.start_event, self.ser.buffer.as_mut()) {
+            (Some(index), Some(buffer)) => buffer.get_mut(index),
+            _ => None,
+        };
+        let (attrs, tag_name) = match (self.start.as_mut(), written, self.tag_name.as_ref()) {
+            (Some(attrs), _, Some(tag_name)) => (attrs, tag_name),
+            (None, Some(Event::StartElement { attrs, .. }), Some(tag_name)) => (attrs, tag_name),
+            _ => return Err(error::with_message("Cannot add attribute".into())),
+        };
+        check_attribute_name(name, tag_name)?;
+        if attrs.iter().any(|(existing, _)| existing == name) {
+            return Err(error::with_message(
+                format!("duplicate attribute {} on element {}", name, tag_name)));
+        }
+        attrs.push((name.to_string(), value));
+        Ok(())
+    }
+
+    /// Writes the start tag. Unless they were read ahead, attributes can
+    /// still be added to it while it is held back.
+    fn start_tag(&mut self) -> Result<()> {
+        match (self.start.take(), self.tag_name.as_ref()) {
+            (Some(attrs), Some(tag_name)) => {
+                self.start_event = self.ser.buffer.as_ref().map(Vec::len);
+                self.ser.start_tag(tag_name, attrs)
+            },
+            _ => Ok(()),
+        }
     }
 
     fn serialize_struct_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        if key.starts_with("@") {
+        if let Some(name) = key.strip_prefix('@') {
             trace!("attribute {}", key);
-            self.ser.add_attr(&key[1..], to_plain_string(value)?)
+            if self.read_ahead {
+                return Ok(());
+            }
+            return match to_attribute_value(name, value, self.ser.format)? {
+                Some(value) => self.add_attr(name, value),
+                None => Ok(()),
+            };
+        }
+
+        self.start_tag()?;
+        if key == "#comment" {
+            trace!("comment");
+            for comment in to_plain_strings(value, self.ser.format)? {
+                self.ser.comment(comment)?;
+            }
+            Ok(())
         } else if key == "." {
-            self.ser.build_start_tag()?;
             trace!("body");
-            value.serialize(&mut *self.ser)?;
-            Ok(())
+            self.ser.element(value)
         } else {
-            self.ser.build_start_tag()?;
             self.ser.open_tag(key)?;
             trace!("field {}", key);
-            value.serialize(&mut *self.ser)?;
+            self.ser.element(value)?;
             trace!("end field");
             Ok(())
         }
     }
 
-    fn after_fields(self) -> Result<()>
+    fn after_fields(mut self) -> Result<()>
     {
-        self.ser.build_start_tag()?;
-        self.ser.end_tag()?;
+        self.start_tag()?;
+        if self.tag_name.is_some() {
+            self.ser.end_tag()?;
+        }
+        if self.buffering {
+            for event in self.ser.buffer.take().unwrap_or_default() {
+                self.ser.write(event)?;
+            }
+        }
         if self.must_close_tag {
             self.ser.end_tag()?;
         }
@@ -1,18 +1,37 @@
+mod builder;
+mod encoding;
 mod map;
 mod plain;
+mod probe;
 mod seq;
 mod tuple;
+mod writer;
 
-use std::collections::HashMap;
 use std::io::Write;
+use std::mem;
 
 use serde::ser::Serialize;
 
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
 use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
 
+use super::binary::BinaryEncoding;
+use super::cdata;
+use super::de::scan::declaration_end;
+use super::list;
+use super::lexical::{LexicalForm, ValueFormat};
+use super::maps::MapRepresentation;
+use super::name;
 use super::error::{self, Result, Error};
 
+pub use self::builder::{SerializerBuilder, XmlVersion};
+pub use self::encoding::Transcoder;
+pub use self::writer::XmlWriter;
+
+use self::builder::{Declaration, PrologItem};
+use self::encoding::OutputEncoding;
 use self::map::{MapSerializer, StructSerializer};
+use self::plain::to_plain_string;
 use self::seq::SeqSeralizer;
 use self::tuple::TupleSerializer;
 
@@ -20,6 +39,18 @@ pub fn to_string<S: Serialize>(value: &S) -> Result<String> {
     to_string_ns(value, None, &[])
 }
 
+pub fn to_string_pretty<S: Serialize>(value: &S) -> Result<String> {
+    SerializerBuilder::new().to_string(value)
+}
+
+pub fn to_string_compact<S: Serialize>(value: &S) -> Result<String> {
+    SerializerBuilder::compact().to_string(value)
+}
+
+pub fn to_vec<S: Serialize>(value: &S) -> Result<Vec<u8>> {
+    SerializerBuilder::new().to_vec(value)
+}
+
 pub fn to_string_ns<S: Serialize>(
     value: &S, default_ns: Option<&str>, namespaces: &[(&str, &str)]
 ) -> Result<String>
@@ -39,7 +70,84 @@ pub fn to_writer_ns<W: Write, S: Serialize>(
     writer: W, value: &S, default_ns: Option<&str>, namespaces: &[(&str, &str)]
 ) -> Result<()> {
     let mut ser = Serializer::new(writer, default_ns, namespaces);
-    value.serialize(&mut ser)
+    ser.element(value)
+}
+
+/// Order in which the attributes of an element are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOrder {
+    /// The order in which the fields are declared.
+    #[default]
+    Declaration,
+    /// Sorted by qualified name.
+    Sorted,
+}
+
+fn check_element_name(name: &str) -> Result<()> {
+    if name::is_element_name(name) {
+        Ok(())
+    } else {
+        Err(error::with_message(format!("{:?} is not a valid XML element name", name)))
+    }
+}
+
+fn check_attribute_name(name: &str, element: &str) -> Result<()> {
+    if name::is_attribute_name(name) {
+        Ok(())
+    } else {
+        Err(error::with_message(format!("{:?} on element {} is not a valid XML attribute name", name, element)))
+    }
+}
+
+/// Comments may not contain `--` nor end with `-`.
+fn is_valid_comment(comment: &str) -> bool {
+    !comment.contains("--") && !comment.ends_with('-')
+}
+
+/// A document type declaration starts with the name of the root element,
+/// and its `>` is the last character written, so no markup follows it.
+fn is_valid_doctype(doctype: &str) -> bool {
+    let name_end = doctype.find(|c: char| c.is_whitespace() || c == '[').unwrap_or(doctype.len());
+    let declaration = format!("<!DOCTYPE {}>", doctype);
+    name::is_name(&doctype[..name_end]) && declaration_end(declaration.as_bytes()) == Some(declaration.len())
+}
+
+/// What to do with characters that XML 1.0 does not allow, such as most
+/// control characters, in text and attribute values.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalCharacters {
+    /// Fail to serialize.
+    #[default]
+    Error,
+    /// Leave them out.
+    Strip,
+    /// Write U+FFFD REPLACEMENT CHARACTER instead.
+    Replace,
+}
+
+impl IllegalCharacters {
+    fn apply(self, s: String) -> Result<String> {
+        match s.chars().find(|&c| !name::is_char(c)) {
+            None => Ok(s),
+            Some(c) => match self {
+                IllegalCharacters::Error => Err(error::with_message(
+                    format!("illegal character U+{:04X} in {:?}", c as u32, s))),
+                IllegalCharacters::Strip => Ok(s.chars().filter(|&c| name::is_char(c)).collect()),
+                IllegalCharacters::Replace => {
+                    Ok(s.chars().map(|c| if name::is_char(c) { c } else { '\u{FFFD}' }).collect())
+                },
+            },
+        }
+    }
+}
+
+/// Markup checked and escaped by `Serializer::write`.
+enum Event {
+    StartElement { name: String, attrs: Vec<(String, String)> },
+    EndElement,
+    Characters(String),
+    CData(String),
+    Comment(String),
 }
 
 pub struct Serializer<W>
@@ -49,14 +157,31 @@ where W: Write {
     default_ns: Option<String>,
     namespaces: Vec<(String, String)>,
     current_tag: String,
-    current_tag_attrs: Option<HashMap<&'static str, String>>,
+    current_tag_attrs: Option<Vec<(String, String)>>,
+    format: ValueFormat,
+    attribute_order: AttributeOrder,
+    map_representation: MapRepresentation,
+    illegal_characters: IllegalCharacters,
+    /// Other than UTF-8, the emitter leaves escaping to the serializer so
+    /// that it can write character references.
+    encoding: OutputEncoding,
+    /// Attribute fields of the struct about to be serialized, read ahead by
+    /// `element`.
+    attributes: Option<Vec<(String, String)>>,
+    /// Events held back while a struct that was not read ahead is written,
+    /// as its attribute fields may follow its other fields.
+    buffer: Option<Vec<Event>>,
+    declaration: Option<Declaration>,
+    prolog: Vec<PrologItem>,
+    line_separator: Option<String>,
+    indent_string: String,
 }
 
 impl<W: Write> Serializer<W> {
     pub fn new_from_writer(
         writer: EventWriter<W>, default_ns: Option<&str>, namespaces: &[(&str, &str)]
     ) -> Self {
-        let namespaces = namespaces.into_iter()
+        let namespaces = namespaces.iter()
             .map(|(prefix, uri)| (prefix.to_string(), uri.to_string()))
             .collect();
         Self {
@@ -66,22 +191,196 @@ impl<W: Write> Serializer<W> {
             namespaces,
             current_tag: "".into(),
             current_tag_attrs: None,
+            format: ValueFormat::default(),
+            attribute_order: AttributeOrder::default(),
+            map_representation: MapRepresentation::default(),
+            illegal_characters: IllegalCharacters::default(),
+            encoding: OutputEncoding::Utf8,
+            attributes: None,
+            buffer: None,
+            declaration: None,
+            prolog: Vec::new(),
+            line_separator: None,
+            indent_string: "  ".into(),
         }
     }
 
     pub fn new(writer: W, default_ns: Option<&str>, namespaces: &[(&str, &str)]) -> Self {
-        Self::new_from_writer(
+        let mut ser = Self::new_from_writer(
             EmitterConfig::new()
             .perform_indent(true)
-            .create_writer(writer), default_ns, namespaces)
+            .create_writer(writer), default_ns, namespaces);
+        ser.line_separator = Some("\n".into());
+        ser
+    }
+
+    /// Sets how byte strings are encoded, base64 by default.
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.format.binary_encoding = binary_encoding;
+        self
+    }
+
+    /// Sets which lexical forms are written for special float values, XML
+    /// Schema ones (`INF`, `-INF`, `NaN`) by default.
+    pub fn with_lexical_form(mut self, lexical_form: LexicalForm) -> Self {
+        self.format.lexical_form = lexical_form;
+        self
+    }
+
+    /// Sets the order in which attributes are written, declaration order by
+    /// default.
+    pub fn with_attribute_order(mut self, attribute_order: AttributeOrder) -> Self {
+        self.attribute_order = attribute_order;
+        self
+    }
+
+    /// Sets how maps are written, one element named after each key by
+    /// default.
+    pub fn with_map_representation(mut self, map_representation: MapRepresentation) -> Self {
+        self.map_representation = map_representation;
+        self
+    }
+
+    /// Sets what happens to characters XML does not allow, an error by
+    /// default.
+    pub fn with_illegal_characters(mut self, illegal_characters: IllegalCharacters) -> Self {
+        self.illegal_characters = illegal_characters;
+        self
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.inner_mut().flush().map_err(error::io)
+    }
+
+    /// Serializes a value, reading the attribute fields of a struct ahead so
+    /// that they can follow its other fields.
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.attributes = probe::attributes(value, self.format)?;
+        let result = value.serialize(&mut *self);
+        self.attributes = None;
+        result
+    }
+
+    fn write_prolog(&mut self) -> Result<()> {
+        let mut wrote_markup = false;
+        if let Some(declaration) = self.declaration.take() {
+            self.writer.write(XmlEvent::StartDocument {
+                version: declaration.version.into(),
+                encoding: Some(declaration.encoding.as_deref().unwrap_or("UTF-8")),
+                standalone: declaration.standalone,
+            }).map_err(error::writer)?;
+            wrote_markup = true;
+        }
+
+        for item in mem::take(&mut self.prolog) {
+            match item {
+                PrologItem::ProcessingInstruction(pi) => {
+                    self.encoding.check(&pi.target, "processing instruction")?;
+                    self.encoding.check(pi.data.as_deref().unwrap_or(""), "processing instruction")?;
+                    self.writer.write(XmlEvent::processing_instruction(&pi.target, pi.data.as_deref()))
+                        .map_err(error::writer)?;
+                },
+                PrologItem::Comment(comment) => {
+                    self.encoding.check(&comment, "comment")?;
+                    self.writer.write(XmlEvent::comment(&comment)).map_err(error::writer)?;
+                },
+                PrologItem::Doctype(doctype) => {
+                    self.encoding.check(&doctype, "document type declaration")?;
+                    // xml-rs has no event for the document type declaration,
+                    // and only breaks lines before markup it wrote after some
+                    // other markup
+                    let separator = self.line_separator.clone().unwrap_or_default();
+                    let (before, after) = if wrote_markup { (separator, String::new()) } else { (String::new(), separator) };
+                    write!(self.writer.inner_mut(), "{}<!DOCTYPE {}>{}", before, doctype, after)
+                        .map_err(error::io)?;
+                },
+            }
+            wrote_markup = true;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, event: Event) -> Result<()> {
+        if let Some(ref mut buffer) = self.buffer {
+            buffer.push(event);
+            return Ok(());
+        }
+        self.write_prolog()?;
+
+        match event {
+            Event::StartElement { name, attrs } => {
+                let illegal_characters = self.illegal_characters;
+                let encoding = self.encoding;
+                encoding.check(&name, "element name")?;
+                let mut attrs = attrs.into_iter()
+                    .map(|(name, value)| {
+                        encoding.check(&name, "attribute name")?;
+                        let value = illegal_characters.apply(value)?;
+                        let value = match encoding {
+                            OutputEncoding::Utf8 => value,
+                            _ => encoding.references(escape_str_attribute(&value)).into_owned(),
+                        };
+                        Ok((name, value))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if self.attribute_order == AttributeOrder::Sorted {
+                    attrs.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+
+                let mut element = attrs.iter().fold(
+                    XmlEvent::start_element(name.as_str()),
+                    |b, (name, value)| b.attr(name.as_str(), value));
+
+                if let Some(default_ns) = self.default_ns.take() {
+                    encoding.check(&default_ns, "namespace")?;
+                    element = element.default_ns(default_ns);
+                }
+                for ns in mem::take(&mut self.namespaces) {
+                    encoding.check(&ns.0, "namespace prefix")?;
+                    encoding.check(&ns.1, "namespace")?;
+                    element = element.ns(ns.0, ns.1)
+                }
+
+                self.writer.write(element).map_err(error::writer)
+            },
+            Event::EndElement => {
+                self.writer.write(XmlEvent::end_element()).map_err(error::writer)
+            },
+            Event::Characters(s) => {
+                let s = self.illegal_characters.apply(s)?;
+                let s = match self.encoding {
+                    OutputEncoding::Utf8 => s,
+                    encoding => encoding.references(escape_str_pcdata(&s)).into_owned(),
+                };
+                self.writer.write(XmlEvent::characters(&s)).map_err(error::writer)
+            },
+            Event::CData(s) => {
+                let s = self.illegal_characters.apply(s)?;
+                self.encoding.check(&s, "CDATA section")?;
+                self.writer.write(XmlEvent::cdata(&s)).map_err(error::writer)
+            },
+            Event::Comment(s) => {
+                let s = self.illegal_characters.apply(s)?;
+                self.encoding.check(&s, "comment")?;
+                self.writer.write(XmlEvent::comment(&s)).map_err(error::writer)
+            },
+        }
     }
 
-    fn next(&mut self, event: XmlEvent) -> Result<()> {
-        self.writer.write(event).map_err(error::writer)
+    fn comment(&mut self, comment: String) -> Result<()> {
+        if !is_valid_comment(&comment) {
+            return Err(error::with_message(format!("invalid comment {:?}", comment)));
+        }
+        self.write(Event::Comment(comment))
     }
 
     fn characters(&mut self, s: &str) -> Result<()> {
-    	self.next(XmlEvent::characters(s))
+    	self.write(Event::Characters(s.to_string()))
     }
 
     fn open_root_tag(&mut self, name: &'static str) -> Result<()> {
@@ -93,14 +392,22 @@ impl<W: Write> Serializer<W> {
     }
 
     fn open_tag(&mut self, tag_name: &str) -> Result<()> {
+        check_element_name(tag_name)?;
         self.current_tag = tag_name.into();
-        self.current_tag_attrs = Some(HashMap::new());
+        self.current_tag_attrs = Some(Vec::new());
         Ok(())
     }
 
-    fn reopen_tag(&mut self) -> Result<()> {
-        self.current_tag_attrs = Some(HashMap::new());
-        Ok(())
+    /// Adds an attribute to the tag opened by `open_tag`.
+    fn add_attr(&mut self, name: String, value: String) -> Result<()> {
+        check_attribute_name(&name, &self.current_tag)?;
+        match self.current_tag_attrs {
+            Some(ref mut attrs) => {
+                attrs.push((name, value));
+                Ok(())
+            },
+            None => Err(error::with_message("Cannot add attribute".into())),
+        }
     }
 
     fn abandon_tag(&mut self) -> Result<()> {
@@ -109,45 +416,26 @@ impl<W: Write> Serializer<W> {
         Ok(())
     }
 
-    fn add_attr(&mut self, name: &'static str, value: String) -> Result<()> {
-        self.current_tag_attrs.as_mut()
-            .ok_or(error::with_message("Cannot add attribute".into()))
-            .map(|attrs| {
-                attrs.insert(name, value);
-            })
-    }
-
     fn build_start_tag(&mut self) -> Result<bool> {
         if let Some(attrs) = self.current_tag_attrs.take() {
-            self.start_tag(&self.current_tag(), attrs)?;
+            let name = self.current_tag();
+            self.start_tag(&name, attrs)?;
             Ok(true)
         } else {
             Ok(false)
         }
     }
 
-    fn start_tag(
-        &mut self,
-        tag_name: &str,
-        attrs: HashMap<&str, String>
-    ) -> Result<()>
-    {
-        let mut element = attrs.iter().fold(
-            XmlEvent::start_element(tag_name),
-            |b, (&name, value)| b.attr(name, value));
-
-        if let Some(default_ns) = self.default_ns.take() {
-            element = element.default_ns(default_ns);
-        }
-        for ns in self.namespaces.drain(..) {
-            element = element.ns(ns.0, ns.1)
+    fn start_tag(&mut self, tag_name: &str, attrs: Vec<(String, String)>) -> Result<()> {
+        check_element_name(tag_name)?;
+        for (name, _) in &attrs {
+            check_attribute_name(name, tag_name)?;
         }
-
-        self.next(element.into())
+        self.write(Event::StartElement { name: tag_name.to_string(), attrs })
     }
 
     fn end_tag(&mut self) -> Result<()> {
-        self.next(XmlEvent::end_element().into())
+        self.write(Event::EndElement)
     }
 
     fn current_tag(&self) -> String {
@@ -219,12 +507,14 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok>
 	{
-		self.serialize_f64(f64::from(v))
+        let formatted = self.format.format_f32(v);
+        self.serialize_str(&formatted)
 	}
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok>
 	{
-        self.serialize_str(&v.to_string())
+        let formatted = self.format.format_f64(v);
+        self.serialize_str(&formatted)
 	}
 
     fn serialize_char(self, v: char) -> Result<Self::Ok>
@@ -242,9 +532,10 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
         Ok(())
 	}
 
-    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok>
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok>
 	{
-		unimplemented!()
+        let encoded = self.format.binary_encoding.encode(v);
+        self.serialize_str(&encoded)
 	}
 
     fn serialize_none(self) -> Result<Self::Ok>
@@ -257,11 +548,11 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
 		Ok(())
 	}
 
-    fn serialize_some<T: ?Sized>(
+    fn serialize_some<T>(
         self,
         value: &T
     ) -> Result<Self::Ok>
-    where T: Serialize
+    where T: ?Sized + Serialize
     {
         trace!("Some");
         value.serialize(self)
@@ -294,22 +585,41 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
     ) -> Result<Self::Ok>
 	{
         trace!("Unit variant {}::{}", name, variant);
+        if self.root {
+            // The root element names the variant
+            self.open_root_tag(variant)?;
+            return self.serialize_unit();
+        }
         self.serialize_str(variant)
 	}
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         name: &'static str,
         value: &T
     ) -> Result<Self::Ok>
     where
-        T: Serialize
+        T: ?Sized + Serialize
 	{
         trace!("Newtype struct {}", name);
+        if name == cdata::NAME {
+            let text = to_plain_string(value, self.format)?;
+            let must_close_tag = self.build_start_tag()?;
+            self.write(Event::CData(text))?;
+            if must_close_tag {
+                self.end_tag()?;
+            }
+            return Ok(());
+        }
+        if name == list::ITEM {
+            let text = to_plain_string(value, self.format)?;
+            list::check_item(&text)?;
+            return self.serialize_str(&text);
+        }
         value.serialize(self)
 	}
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         name: &'static str,
         _variant_index: u32,
@@ -317,13 +627,16 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
         value: &T
     ) -> Result<Self::Ok>
     where
-        T: Serialize
+        T: ?Sized + Serialize
 	{
-        let must_close_tag = self.build_start_tag()?;
-
         trace!("Newtype variant {}::{}", name, variant);
+        if self.root {
+            self.open_root_tag(variant)?;
+            return self.element(value);
+        }
+        let must_close_tag = self.build_start_tag()?;
         self.open_tag(variant)?;
-        value.serialize(&mut *self)?;
+        self.element(value)?;
 
         if must_close_tag {
             self.end_tag()?;
@@ -370,8 +683,14 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
     ) -> Result<Self::SerializeTupleVariant>
 	{
         trace!("Tuple variant {}::{}", name, variant);
+        if self.root {
+            self.open_root_tag(variant)?;
+            // Closed with the variant
+            self.build_start_tag()?;
+            return Ok(TupleSerializer::new(self, false));
+        }
         let must_close_tag = self.build_start_tag()?;
-        self.start_tag(variant, HashMap::new())?;
+        self.start_tag(variant, Vec::new())?;
         Ok(TupleSerializer::new(self, must_close_tag))
 	}
 
@@ -392,7 +711,7 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
         self.open_root_tag(name)?;
 
         trace!("Struct {}", name);
-        Ok(StructSerializer::new(self, false))
+        StructSerializer::new(self, false)
     }
 
     fn serialize_struct_variant(
@@ -402,12 +721,14 @@ impl<'ser, W: Write> serde::ser::Serializer for &'ser mut Serializer<W> {
         variant: &'static str,
         _len: usize
     ) -> Result<Self::SerializeStructVariant> {
-        self.open_root_tag(name)?;
-
-        trace!("Struct variant {}", variant);
+        trace!("Struct variant {}::{}", name, variant);
+        if self.root {
+            self.open_root_tag(variant)?;
+            return StructSerializer::new(self, false);
+        }
         let must_close_tag = self.build_start_tag()?;
         self.open_tag(variant)?;
-        Ok(StructSerializer::new(self, must_close_tag))
+        StructSerializer::new(self, must_close_tag)
     }
 }
 
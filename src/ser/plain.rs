@@ -1,25 +1,76 @@
 use std::io::Write;
 
-use serde::ser::{Impossible, Serialize};
+use serde::ser::{Impossible, Serialize, SerializeSeq, SerializeTuple, SerializeTupleStruct};
 
+use super::super::lexical::ValueFormat;
+use super::super::list;
 use super::super::error::{self, Result, Error};
 
-pub fn to_plain_string<T>(value: &T) -> Result<String>
+pub fn to_plain_string<T>(value: &T, format: ValueFormat) -> Result<String>
+where T: ?Sized + Serialize {
+    to_optional_plain_string(value, format)?
+        .ok_or_else(|| error::with_message("expected a value but got none".to_string()))
+}
+
+/// Like `to_plain_string`, but yields `None` when the value serializes as
+/// `None`, so that callers can leave out optional attributes.
+pub fn to_optional_plain_string<T>(value: &T, format: ValueFormat) -> Result<Option<String>>
 where T: ?Sized + Serialize {
     let mut writer = Vec::with_capacity(128);
-    value.serialize(&mut PlainStringSerializer::new(&mut writer))?;
+    let mut ser = PlainStringSerializer::new(&mut writer, format);
+    value.serialize(&mut ser)?;
+
+    if ser.none {
+        return Ok(None);
+    }
+
+    let string = String::from_utf8(writer).map_err(error::from_utf8)?;
+    Ok(Some(string))
+}
+
+/// Value of the attribute `name`, pointing a sequence to `List`, whose items
+/// read back.
+pub fn to_attribute_value<T>(name: &str, value: &T, format: ValueFormat) -> Result<Option<String>>
+where T: ?Sized + Serialize {
+    match to_optional_plain_string(value, format) {
+        Err(_) if to_plain_strings(value, format).is_ok() => Err(error::with_message(format!(
+            "attribute {} is a sequence, use serde_xml::List for whitespace-separated items", name))),
+        result => result,
+    }
+}
+
+/// Like `to_optional_plain_string`, but yields each item of a sequence as a
+/// string of its own, as for the comments of an element.
+pub fn to_plain_strings<T>(value: &T, format: ValueFormat) -> Result<Vec<String>>
+where T: ?Sized + Serialize {
+    let mut writer = Vec::with_capacity(128);
+    let mut ser = PlainStringSerializer::new(&mut writer, format);
+    ser.items = Some(Vec::new());
+    value.serialize(&mut ser)?;
+
+    if ser.none {
+        return Ok(Vec::new());
+    }
+    if ser.sequence {
+        return Ok(ser.items.take().unwrap_or_default());
+    }
 
     let string = String::from_utf8(writer).map_err(error::from_utf8)?;
-    Ok(string)
+    Ok(vec![string])
 }
 
 struct PlainStringSerializer<W: Write> {
-    writer: W
+    writer: W,
+    none: bool,
+    format: ValueFormat,
+    /// Items of a sequence, if sequences are accepted.
+    items: Option<Vec<String>>,
+    sequence: bool,
 }
 
 impl<W: Write> PlainStringSerializer<W> {
-    fn new(writer: W) -> Self {
-        PlainStringSerializer { writer }
+    fn new(writer: W, format: ValueFormat) -> Self {
+        PlainStringSerializer { writer, none: false, format, items: None, sequence: false }
     }
     
     fn characters(&mut self, s: &str) -> Result<()> {
@@ -32,9 +83,9 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
-    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
-    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeSeq = PlainSeqSerializer<'ser, W>;
+    type SerializeTuple = PlainTupleSerializer<'ser, W>;
+    type SerializeTupleStruct = PlainTupleSerializer<'ser, W>;
     type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
     type SerializeMap = Impossible<Self::Ok, Self::Error>;
     type SerializeStruct = Impossible<Self::Ok, Self::Error>;
@@ -87,12 +138,14 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
     
     fn serialize_f32(self, v: f32) -> Result<Self::Ok>
 	{
-		self.serialize_f64(f64::from(v))
+        let formatted = self.format.format_f32(v);
+		self.characters(&formatted)
 	}
     
     fn serialize_f64(self, v: f64) -> Result<Self::Ok>
 	{
-		self.characters(&v.to_string())
+        let formatted = self.format.format_f64(v);
+		self.characters(&formatted)
 	}
     
     fn serialize_char(self, v: char) -> Result<Self::Ok>
@@ -105,28 +158,30 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         self.characters(v)
 	}
     
-    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok>
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok>
 	{
-		unimplemented!()
+        let encoded = self.format.binary_encoding.encode(v);
+		self.characters(&encoded)
 	}
     
     fn serialize_none(self) -> Result<Self::Ok>
 	{
-		unimplemented!()
+        self.none = true;
+		Ok(())
 	}
     
-    fn serialize_some<T: ?Sized>(
+    fn serialize_some<T>(
         self,
-        _value: &T
+        value: &T
     ) -> Result<Self::Ok>
-    where T: Serialize
+    where T: ?Sized + Serialize
     {
-        unimplemented!()
+        value.serialize(self)
     }
     
     fn serialize_unit(self) -> Result<Self::Ok>
 	{
-		unimplemented!()
+		Ok(())
 	}
     
     fn serialize_unit_struct(
@@ -134,31 +189,36 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _name: &'static str
     ) -> Result<Self::Ok>
 	{
-		unimplemented!()
+		self.serialize_unit()
 	}
     
     fn serialize_unit_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str
+        variant: &'static str
     ) -> Result<Self::Ok>
 	{
-        unimplemented!()
+        self.characters(variant)
 	}
     
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
-        _value: &T
+        name: &'static str,
+        value: &T
     ) -> Result<Self::Ok>
     where
-        T: Serialize
+        T: ?Sized + Serialize
 	{
-        unimplemented!()
+        if name == list::ITEM {
+            let text = to_plain_string(value, self.format)?;
+            list::check_item(&text)?;
+            return self.characters(&text);
+        }
+        value.serialize(self)
 	}
     
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
@@ -166,9 +226,9 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _value: &T
     ) -> Result<Self::Ok>
     where
-        T: Serialize
+        T: ?Sized + Serialize
 	{
-        unimplemented!()
+        Err(not_plain("a newtype variant"))
 	}
     
     fn serialize_seq(
@@ -176,7 +236,11 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _len: Option<usize>
     ) -> Result<Self::SerializeSeq>
 	{
-		unimplemented!()
+        if self.items.is_none() || self.sequence {
+            return Err(not_plain("a sequence"));
+        }
+        self.sequence = true;
+		Ok(PlainSeqSerializer { ser: self })
 	}
     
     fn serialize_tuple(
@@ -184,7 +248,7 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _len: usize
     ) -> Result<Self::SerializeTuple>
 	{
-		unimplemented!()
+		Ok(PlainTupleSerializer { ser: self, first: true })
 	}
     
     fn serialize_tuple_struct(
@@ -193,7 +257,7 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _len: usize
     ) -> Result<Self::SerializeTupleStruct>
 	{
-		unimplemented!()
+		Ok(PlainTupleSerializer { ser: self, first: true })
 	}
     
     fn serialize_tuple_variant(
@@ -204,7 +268,7 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _len: usize
     ) -> Result<Self::SerializeTupleVariant>
 	{
-		unimplemented!()
+		Err(not_plain("a tuple variant"))
 	}
     
     fn serialize_map(
@@ -212,7 +276,7 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _len: Option<usize>
     ) -> Result<Self::SerializeMap>
 	{
-		unimplemented!()
+		Err(not_plain("a map"))
 	}
     
     fn serialize_struct(
@@ -220,7 +284,7 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _name: &'static str,
         _len: usize
     ) -> Result<Self::SerializeStruct> {
-        unimplemented!()
+        Err(not_plain("a struct"))
     }
 
     fn serialize_struct_variant(
@@ -230,6 +294,83 @@ impl<'ser, W: 'ser + Write> serde::ser::Serializer for &'ser mut PlainStringSeri
         _variant: &'static str,
         _len: usize
     ) -> Result<Self::SerializeStructVariant> {
-        unimplemented!()
+        Err(not_plain("a struct variant"))
+    }
+}
+
+fn not_plain(what: &str) -> Error {
+    error::with_message(format!("{} cannot be written as text", what))
+}
+
+struct PlainSeqSerializer<'ser, W: 'ser + Write> {
+    ser: &'ser mut PlainStringSerializer<W>,
+}
+
+impl<'ser, W: 'ser + Write> SerializeSeq for PlainSeqSerializer<'ser, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let item = to_plain_string(value, self.ser.format)?;
+        self.ser.items.as_mut().expect("checked by serialize_seq").push(item);
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct PlainTupleSerializer<'ser, W: 'ser + Write> {
+    ser: &'ser mut PlainStringSerializer<W>,
+    first: bool,
+}
+
+impl<'ser, W: 'ser + Write> PlainTupleSerializer<'ser, W> {
+    fn serialize_item<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.first {
+            self.first = false;
+        } else {
+            self.ser.characters(" ")?;
+        }
+        value.serialize(&mut *self.ser)
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeTuple for PlainTupleSerializer<'ser, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serialize_item(value)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeTupleStruct for PlainTupleSerializer<'ser, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serialize_item(value)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
     }
 }
@@ -0,0 +1,177 @@
+use serde::ser::{self, Serialize};
+
+use super::super::lexical::ValueFormat;
+use super::error::{Error, Result};
+use super::plain::to_attribute_value;
+
+type Attributes = Vec<(String, String)>;
+
+/// Attribute fields of the struct `value` serializes as, if it does, so that
+/// they can be written on the start tag whatever the order of the fields.
+pub fn attributes<T>(value: &T, format: ValueFormat) -> Result<Option<Attributes>>
+where T: ?Sized + Serialize {
+    value.serialize(AttributeProbe { format })
+}
+
+struct AttributeProbe {
+    format: ValueFormat,
+}
+
+pub struct StructProbe {
+    format: ValueFormat,
+    attributes: Attributes,
+}
+
+impl StructProbe {
+    fn field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        if let Some(name) = key.strip_prefix('@') {
+            if let Some(value) = to_attribute_value(name, value, self.format)? {
+                self.attributes.push((name.to_string(), value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Anything but a struct, which has no attribute fields.
+pub struct Ignored;
+
+impl ser::Serializer for AttributeProbe {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    type SerializeSeq = Ignored;
+    type SerializeTuple = Ignored;
+    type SerializeTupleStruct = Ignored;
+    type SerializeTupleVariant = Ignored;
+    type SerializeMap = Ignored;
+    type SerializeStruct = StructProbe;
+    type SerializeStructVariant = StructProbe;
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_none(self) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Ok(None) }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, _variant: &'static str) -> Result<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    /// The variant is an element of its own, whose attributes are read
+    /// ahead when it is written.
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T
+    ) -> Result<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Ignored> { Ok(Ignored) }
+    fn serialize_tuple(self, _len: usize) -> Result<Ignored> { Ok(Ignored) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Ignored> { Ok(Ignored) }
+
+    fn serialize_tuple_variant(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize
+    ) -> Result<Ignored> {
+        Ok(Ignored)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Ignored> { Ok(Ignored) }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<StructProbe> {
+        Ok(StructProbe { format: self.format, attributes: Vec::new() })
+    }
+
+    fn serialize_struct_variant(
+        self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize
+    ) -> Result<StructProbe> {
+        Ok(StructProbe { format: self.format, attributes: Vec::new() })
+    }
+}
+
+impl ser::SerializeStruct for StructProbe {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(Some(self.attributes))
+    }
+}
+
+impl ser::SerializeStructVariant for StructProbe {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(Some(self.attributes))
+    }
+}
+
+impl ser::SerializeSeq for Ignored {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> { Ok(()) }
+    fn end(self) -> Result<Self::Ok> { Ok(None) }
+}
+
+impl ser::SerializeTuple for Ignored {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> { Ok(()) }
+    fn end(self) -> Result<Self::Ok> { Ok(None) }
+}
+
+impl ser::SerializeTupleStruct for Ignored {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> { Ok(()) }
+    fn end(self) -> Result<Self::Ok> { Ok(None) }
+}
+
+impl ser::SerializeTupleVariant for Ignored {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> { Ok(()) }
+    fn end(self) -> Result<Self::Ok> { Ok(None) }
+}
+
+impl ser::SerializeMap for Ignored {
+    type Ok = Option<Attributes>;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, _key: &T) -> Result<()> { Ok(()) }
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<()> { Ok(()) }
+    fn end(self) -> Result<Self::Ok> { Ok(None) }
+}
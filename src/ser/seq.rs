@@ -6,12 +6,15 @@ use super::error::{Result, Error};
 use super::Serializer;
 
 pub struct SeqSeralizer<'ser, W: 'ser + Write> {
-    ser: &'ser mut Serializer<W>
+    ser: &'ser mut Serializer<W>,
+    tag_name: String,
+    first: bool,
 }
 
 impl<'ser, W: 'ser + Write> SeqSeralizer<'ser, W> {
     pub fn new(ser: &'ser mut Serializer<W>) -> Self {
-        SeqSeralizer { ser }
+        let tag_name = ser.current_tag();
+        SeqSeralizer { ser, tag_name, first: true }
     }
 }
 
@@ -23,13 +26,13 @@ impl<'ser, W: 'ser + Write> serde::ser::SerializeSeq for SeqSeralizer<'ser, W> {
     where
         T: ?Sized + Serialize,
     {
-        let must_close_tag = self.ser.build_start_tag()?;
-        value.serialize(&mut *self.ser)?;
-        if must_close_tag {
-            self.ser.end_tag()?;
-            self.ser.reopen_tag()?;
+        // Each item is written as its own element, named after the field
+        if self.first {
+            self.first = false;
+        } else {
+            self.ser.open_tag(&self.tag_name)?;
         }
-        Ok(())
+        self.ser.element(value)
     }
 
     fn end(self) -> Result<()> {
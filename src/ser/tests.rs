@@ -1,6 +1,8 @@
+#![allow(clippy::upper_case_acronyms)]
+
 use std::collections::HashMap;
 
-pub use super::{to_string, to_string_ns, Serializer};
+pub use super::{to_string, to_string_ns, AttributeOrder, Serializer};
 use xml::writer::{EmitterConfig, XmlEvent};
 use super::error;
 use serde::ser::Serialize;
@@ -24,7 +26,7 @@ fn one_element() {
     let input = Document { value: "plain text".to_string() };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <value>plain text</value>
         </document>"#);
@@ -99,7 +101,7 @@ fn nested_elements() {
     let input = Document { inner: InnerElement { value: "plain text".to_string() } };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <inner>
             <value>plain text</value>
@@ -128,7 +130,7 @@ fn multiple_elements() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <first>plain text</first>
           <second>more text</second>
@@ -147,7 +149,7 @@ fn map() {
     #[serde(rename = "document", rename_all = "kebab-case")]
     struct Document {
         content: HashMap<String, String>
-    };
+    }
 
     let input = Document {
         content: [
@@ -157,7 +159,7 @@ fn map() {
     };
 
     let expected1 = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>
             <first.key>plain text</first.key>
@@ -166,7 +168,7 @@ fn map() {
         </document>"#);
 
     let expected2 = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>
             <second-key>more text</second-key>
@@ -178,7 +180,7 @@ fn map() {
 
     trace!("Actual {:?}", actual);
 
-    assert!(vec![expected1.to_string(), expected2.to_string()].contains(&actual));
+    assert!([expected1.to_string(), expected2.to_string()].contains(&actual));
 }
 
 #[test]
@@ -197,7 +199,7 @@ fn sequence() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <item>first</item>
           <item>second</item>
@@ -231,7 +233,7 @@ fn unit_variant() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>a</content>
         </document>"#);
@@ -277,7 +279,7 @@ fn struct_variant() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>
             <trump>
@@ -315,7 +317,7 @@ fn newtype_variant() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>
             <i>42</i>
@@ -352,7 +354,7 @@ fn tuple_variant() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>
             <kv>abc 123</kv>
@@ -383,7 +385,7 @@ fn tuple_struct() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>abc 123</content>
         </document>"#);
@@ -408,7 +410,7 @@ fn tuple() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>123 1.23 abc</content>
         </document>"#);
@@ -418,6 +420,40 @@ fn tuple() {
     assert_eq!(expected, actual);
 }
 
+#[test]
+fn tuple_typed_values() {
+    setup();
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    enum Axis {
+        Y,
+    }
+
+    #[derive(Debug, PartialEq, Serialize)]
+    struct Millimetres(f64);
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "document", rename_all = "kebab-case")]
+    struct Document {
+        content: (Axis, Millimetres, char, bool),
+    }
+
+    let input = Document {
+        content: (Axis::Y, Millimetres(1.5), 'a', true),
+    };
+
+    let expected = indoc!(r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <document>
+          <content>y 1.5 a true</content>
+        </document>"#);
+
+    let actual = to_string(&input).unwrap();
+
+    assert_eq!(expected, actual);
+}
+
 #[test]
 fn types_unit() {
     setup();
@@ -433,7 +469,7 @@ fn types_unit() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content />
         </document>"#);
@@ -462,7 +498,7 @@ fn unit_struct() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content />
         </document>"#);
@@ -491,7 +527,7 @@ fn newtype_struct() {
     };
 
     let expected = indoc!(r#"
-        <?xml version="1.0" encoding="utf-8"?>
+        <?xml version="1.0" encoding="UTF-8"?>
         <document>
           <content>abc</content>
         </document>"#);
@@ -519,7 +555,7 @@ mod option {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document>
               <content />
             </document>"#);
@@ -538,7 +574,7 @@ mod option {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document>
               <content>123</content>
             </document>"#);
@@ -574,7 +610,7 @@ mod attribute {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document>
               <content id="123" />
             </document>"#);
@@ -610,22 +646,222 @@ mod attribute {
             },
         };
 
-        let expected1 = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
             <document>
               <content x="20" y="40" />
             </document>"#);
-        let expected2 = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
-            <document>
-              <content y="40" x="20" />
+
+        let actual = to_string(&input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn after_children() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Entity {
+            #[serde(rename = ".")]
+            text: String,
+            #[serde(rename = "@id")]
+            id: String,
+        }
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            content: Entity,
+            #[serde(rename = "item")]
+            items: Vec<Entity>,
+            #[serde(rename = "@version")]
+            version: String,
+        }
+
+        let input = Document {
+            content: Entity { text: "abc".to_string(), id: "123".to_string() },
+            items: vec![
+                Entity { text: "first".to_string(), id: "1".to_string() },
+                Entity { text: "second".to_string(), id: "2".to_string() },
+            ],
+            version: "1.2.3".to_string(),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document version="1.2.3">
+              <content id="123">abc</content>
+              <item id="1">first</item>
+              <item id="2">second</item>
             </document>"#);
 
         let actual = to_string(&input).unwrap();
 
-        trace!("{:?}", actual);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn declaration_order() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@zulu")]
+            zulu: u32,
+            #[serde(rename = "@alpha")]
+            alpha: u32,
+            #[serde(rename = "@mike")]
+            mike: u32,
+            #[serde(rename = "@bravo")]
+            bravo: u32,
+        }
+
+        let input = Document { zulu: 1, alpha: 2, mike: 3, bravo: 4 };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document zulu="1" alpha="2" mike="3" bravo="4" />"#);
+
+        for _ in 0..10 {
+            assert_eq!(expected, to_string(&input).unwrap());
+        }
+    }
+
+    #[test]
+    fn sorted() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@zulu")]
+            zulu: u32,
+            #[serde(rename = "@alpha")]
+            alpha: u32,
+            content: String,
+        }
+
+        let input = Document { zulu: 1, alpha: 2, content: "abc".to_string() };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document alpha="2" zulu="1">
+              <content>abc</content>
+            </document>"#);
+
+        let mut buf = Vec::new();
+        {
+            let mut ser = Serializer::new(&mut buf, None, &[])
+                .with_attribute_order(AttributeOrder::Sorted);
+            input.serialize(&mut ser).unwrap();
+        }
+        let actual = String::from_utf8(buf).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn duplicate() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@id")]
+            id: u32,
+            #[serde(rename = "@id")]
+            other_id: u32,
+        }
+
+        let input = Document { id: 1, other_id: 2 };
+
+        assert!(to_string(&input).is_err());
+    }
+
+    #[test]
+    fn streamed() {
+        setup();
+
+        use std::cell::RefCell;
+        use std::io::{self, Write};
+        use std::rc::Rc;
+
+        #[derive(Clone)]
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        /// Checks what has been written by the time it is serialized.
+        struct Written(Shared, &'static str);
+
+        impl Serialize for Written {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let written = String::from_utf8((self.0).0.borrow().clone()).unwrap();
+                assert!(written.contains(self.1), "{:?}", written);
+                serializer.serialize_str("b")
+            }
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename = "document")]
+        struct Document {
+            a: String,
+            b: Written,
+            #[serde(rename = "@id")]
+            id: u32,
+        }
+
+        let output = Shared(Rc::new(RefCell::new(Vec::new())));
+        let input = Document { a: "a".to_string(), b: Written(output.clone(), r#"<document id="1"><a>a</a>"#), id: 1 };
+        crate::ser::SerializerBuilder::compact().to_writer(output.clone(), &input).unwrap();
 
-        assert!(vec![expected1.to_string(), expected2.to_string()].contains(&actual));
+        let written = String::from_utf8(output.0.borrow().clone()).unwrap();
+        assert!(written.ends_with(r#"<document id="1"><a>a</a><b>b</b></document>"#), "{}", written);
+    }
+
+    #[test]
+    fn root_without_read_ahead() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "document")]
+        struct Late {
+            content: String,
+            #[serde(rename = "@id")]
+            id: u32,
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename = "document")]
+        struct Early {
+            #[serde(rename = "@id")]
+            id: u32,
+            content: String,
+        }
+
+        let mut buf = Vec::new();
+        let early = Early { id: 1, content: "abc".to_string() };
+        early.serialize(&mut Serializer::new(&mut buf, None, &[])).unwrap();
+        assert!(String::from_utf8(buf).unwrap().contains(r#"<document id="1">"#));
+
+        let mut buf = Vec::new();
+        let late = Late { content: "abc".to_string(), id: 1 };
+        late.serialize(&mut Serializer::new(&mut buf, None, &[])).unwrap();
+        assert!(String::from_utf8(buf).unwrap().ends_with(indoc!(r#"
+            <document id="1">
+              <content>abc</content>
+            </document>"#)));
     }
 
     #[test]
@@ -644,7 +880,7 @@ mod attribute {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document version="1.2.3" />"#);
 
         let actual = to_string(&input).unwrap();
@@ -670,7 +906,7 @@ mod attribute {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document version="1.2.3">
               <content>abc</content>
             </document>"#);
@@ -707,7 +943,7 @@ mod attribute {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document>
               <content id="123">abc</content>
             </document>"#);
@@ -716,6 +952,1297 @@ mod attribute {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn optional_absent() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@count")]
+            count: Option<u32>,
+            content: String,
+        }
+
+        let input = Document {
+            count: None,
+            content: "abc".to_string(),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document>
+              <content>abc</content>
+            </document>"#);
+
+        let actual = to_string(&input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn optional_present() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@count")]
+            count: Option<u32>,
+            content: String,
+        }
+
+        let input = Document {
+            count: Some(42),
+            content: "abc".to_string(),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document count="42">
+              <content>abc</content>
+            </document>"#);
+
+        let actual = to_string(&input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn typed_values() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        enum Status {
+            #[serde(rename = "on-hold")]
+            OnHold,
+        }
+
+        #[derive(Debug, PartialEq, Serialize)]
+        struct OrderId(u32);
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@status")]
+            status: Status,
+        }
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Order {
+            #[serde(rename = "@id")]
+            id: OrderId,
+        }
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Grade {
+            #[serde(rename = "@grade")]
+            grade: char,
+        }
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document status="on-hold" />"#);
+
+        assert_eq!(expected, to_string(&Document { status: Status::OnHold }).unwrap());
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document id="42" />"#);
+
+        assert_eq!(expected, to_string(&Order { id: OrderId(42) }).unwrap());
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document grade="B" />"#);
+
+        assert_eq!(expected, to_string(&Grade { grade: 'B' }).unwrap());
+    }
+}
+
+mod list {
+    use super::*;
+
+    use crate::List;
+
+    #[test]
+    fn attribute() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@coords", with = "crate::list")]
+            coords: Vec<f64>,
+        }
+
+        let input = Document {
+            coords: vec![1.5, 2.0, 3.25],
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document coords="1.5 2 3.25" />"#);
+
+        let actual = to_string(&input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn element() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            tags: List<String>,
+            sizes: List<u32>,
+        }
+
+        let input = Document {
+            tags: List(vec!["red".to_string(), "green".to_string(), "blue".to_string()]),
+            sizes: List(vec![]),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document>
+              <tags>red green blue</tags>
+              <sizes />
+            </document>"#);
+
+        let actual = to_string(&input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn items_that_would_not_read_back() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "document")]
+        struct Document {
+            #[serde(rename = "@names", with = "crate::list")]
+            names: Vec<String>,
+            tags: List<String>,
+        }
+
+        let document = |names: &[&str], tags: &[&str]| Document {
+            names: names.iter().map(|s| s.to_string()).collect(),
+            tags: List(tags.iter().map(|s| s.to_string()).collect()),
+        };
+
+        assert!(to_string(&document(&["a", "b"], &["c", "d"])).is_ok());
+
+        let err = to_string(&document(&["a b"], &[])).unwrap_err();
+        assert_eq!(r#"list item "a b" contains whitespace"#, err.to_string());
+        assert!(to_string(&document(&["a", ""], &[])).is_err());
+        assert!(to_string(&document(&[], &["c\td"])).is_err());
+        let err = to_string(&document(&[], &["c", ""])).unwrap_err();
+        assert_eq!("empty list item", err.to_string());
+    }
+
+    #[test]
+    fn plain_vec_attribute() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "document")]
+        struct Document {
+            #[serde(rename = "@coords")]
+            coords: Vec<f64>,
+        }
+
+        let err = to_string(&Document { coords: vec![1.5, 2.0] }).unwrap_err();
+        assert_eq!("attribute coords is a sequence, use serde_xml::List for whitespace-separated items", err.to_string());
+    }
+}
+
+mod bytes {
+    use super::*;
+
+    use serde_bytes::ByteBuf;
+
+    use crate::BinaryEncoding;
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "document", rename_all = "kebab-case")]
+    struct Document {
+        #[serde(rename = "@hash", with = "serde_bytes")]
+        hash: Vec<u8>,
+        attachment: ByteBuf,
+    }
+
+    #[test]
+    fn base64() {
+        setup();
+
+        let input = Document {
+            hash: vec![0xde, 0xad, 0xbe, 0xef],
+            attachment: ByteBuf::from(b"Hello, world!".to_vec()),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document hash="3q2+7w==">
+              <attachment>SGVsbG8sIHdvcmxkIQ==</attachment>
+            </document>"#);
+
+        let actual = to_string(&input).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn hex() {
+        setup();
+
+        let input = Document {
+            hash: vec![0xde, 0xad, 0xbe, 0xef],
+            attachment: ByteBuf::from(vec![0x01, 0x0a]),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document hash="DEADBEEF">
+              <attachment>010A</attachment>
+            </document>"#);
+
+        let mut buf = Vec::new();
+        {
+            let mut ser = Serializer::new(&mut buf, None, &[])
+                .with_binary_encoding(BinaryEncoding::Hex);
+            input.serialize(&mut ser).unwrap();
+        }
+        let actual = String::from_utf8(buf).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+}
+
+mod lexical {
+    use super::*;
+
+    use crate::LexicalForm;
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "document", rename_all = "kebab-case")]
+    struct Document {
+        #[serde(rename = "@high")]
+        high: f64,
+        low: f64,
+        missing: f32,
+        ratio: f32,
+    }
+
+    const INPUT: Document = Document {
+        high: f64::INFINITY,
+        low: f64::NEG_INFINITY,
+        missing: f32::NAN,
+        ratio: 1.1,
+    };
+
+    #[test]
+    fn xsd() {
+        setup();
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document high="INF">
+              <low>-INF</low>
+              <missing>NaN</missing>
+              <ratio>1.1</ratio>
+            </document>"#);
+
+        let actual = to_string(&INPUT).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rust() {
+        setup();
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document high="inf">
+              <low>-inf</low>
+              <missing>NaN</missing>
+              <ratio>1.1</ratio>
+            </document>"#);
+
+        let mut buf = Vec::new();
+        {
+            let mut ser = Serializer::new(&mut buf, None, &[])
+                .with_lexical_form(LexicalForm::Rust);
+            INPUT.serialize(&mut ser).unwrap();
+        }
+        let actual = String::from_utf8(buf).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+}
+
+mod builder {
+    use super::*;
+
+    use crate::{to_string_compact, to_string_pretty, to_vec, SerializerBuilder, XmlVersion};
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "document", rename_all = "kebab-case")]
+    struct Document {
+        content: String,
+        empty: (),
+    }
+
+    fn input() -> Document {
+        Document { content: "abc".to_string(), empty: () }
+    }
+
+    #[test]
+    fn pretty() {
+        setup();
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <document>
+              <content>abc</content>
+              <empty />
+            </document>"#);
+
+        assert_eq!(expected, to_string_pretty(&input()).unwrap());
+        assert_eq!(expected.as_bytes(), &to_vec(&input()).unwrap()[..]);
+    }
+
+    #[test]
+    fn compact() {
+        setup();
+
+        let expected = concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<document><content>abc</content><empty /></document>"#);
+
+        assert_eq!(expected, to_string_compact(&input()).unwrap());
+    }
+
+    #[test]
+    fn indentation() {
+        setup();
+
+        let expected = "<document>\r\n\t<content>abc</content>\r\n\t<empty />\r\n</document>";
+
+        let actual = SerializerBuilder::new()
+            .indent_string("\t")
+            .line_separator("\r\n")
+            .xml_declaration(false)
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn not_self_closing() {
+        setup();
+
+        let expected = "<document><content>abc</content><empty></empty></document>";
+
+        let actual = SerializerBuilder::compact()
+            .self_closing(false)
+            .xml_declaration(false)
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn declaration() {
+        setup();
+
+        let expected = concat!(
+            r#"<?xml version="1.1" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<document><content>abc</content><empty /></document>"#);
+
+        let actual = SerializerBuilder::compact()
+            .xml_version(XmlVersion::Version11)
+            .encoding("UTF-8")
+            .standalone(true)
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn namespaces() {
+        setup();
+
+        let expected = concat!(
+            r#"<document xmlns="urn:example:document" xmlns:x="urn:example:x">"#,
+            r#"<content>abc</content><empty /></document>"#);
+
+        let actual = SerializerBuilder::compact()
+            .xml_declaration(false)
+            .default_namespace("urn:example:document")
+            .namespace("x", "urn:example:x")
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+}
+
+mod encoding {
+    use super::*;
+
+    use crate::SerializerBuilder;
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "payment")]
+    struct Payment {
+        #[serde(rename = "@currency")]
+        currency: String,
+        payee: String,
+    }
+
+    fn input() -> Payment {
+        Payment { currency: "€".to_string(), payee: "Zoë 東".to_string() }
+    }
+
+    #[test]
+    fn latin1() {
+        setup();
+
+        let mut expected = br#"<?xml version="1.0" encoding="ISO-8859-1"?>"#.to_vec();
+        expected.extend_from_slice(b"<payment currency=\"&#8364;\"><payee>Zo\xEB &#26481;</payee></payment>");
+
+        let actual = SerializerBuilder::compact()
+            .encoding("ISO-8859-1")
+            .to_vec(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn windows_1252() {
+        setup();
+
+        let mut expected = br#"<?xml version="1.0" encoding="windows-1252"?>"#.to_vec();
+        expected.extend_from_slice(b"<payment currency=\"\x80\"><payee>Zo\xEB &#26481;</payee></payment>");
+
+        let actual = SerializerBuilder::compact()
+            .encoding("windows-1252")
+            .to_vec(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn escaping() {
+        setup();
+
+        let input = Payment { currency: "\"€\" & <$>".to_string(), payee: "<Zoë> & 東".to_string() };
+        let mut expected = br#"<?xml version="1.0" encoding="ISO-8859-1"?>"#.to_vec();
+        expected.extend_from_slice(
+            b"<payment currency=\"&quot;&#8364;&quot; &amp; &lt;$&gt;\"><payee>&lt;Zo\xEB&gt; &amp; &#26481;</payee></payment>");
+
+        let actual = SerializerBuilder::compact()
+            .encoding("ISO-8859-1")
+            .to_vec(&input)
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn unmappable_markup() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "東京")]
+        struct Name {}
+
+        #[derive(Serialize)]
+        struct Attribute {
+            #[serde(rename = "@東")]
+            value: u8,
+        }
+
+        #[derive(Serialize)]
+        struct Section {
+            body: crate::CData<String>,
+        }
+
+        #[derive(Serialize)]
+        struct Comment {
+            #[serde(rename = "#comment")]
+            comment: String,
+        }
+
+        let builder = SerializerBuilder::compact().encoding("ISO-8859-1");
+        assert!(builder.to_vec(&Name {}).is_err());
+        assert!(builder.to_vec(&Attribute { value: 1 }).is_err());
+        assert!(builder.to_vec(&Section { body: crate::CData("東".to_string()) }).is_err());
+        assert!(builder.to_vec(&Comment { comment: "東".to_string() }).is_err());
+        assert!(builder.clone().comment("東").to_vec(&input()).is_err());
+        assert!(builder.clone().processing_instruction("pi", Some("東")).to_vec(&input()).is_err());
+
+        // Fine where the encoding has them
+        let section = Section { body: crate::CData("Zoë".to_string()) };
+        assert_eq!(b"<Section><body><![CDATA[Zo\xEB]]></body></Section>".to_vec(),
+            builder.clone().xml_declaration(false).to_vec(&section).unwrap());
+    }
+
+    #[test]
+    fn to_string() {
+        setup();
+
+        let result = SerializerBuilder::new()
+            .encoding("ISO-8859-1")
+            .to_string(&input());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unsupported() {
+        setup();
+
+        let result = SerializerBuilder::new()
+            .encoding("UTF-16")
+            .to_vec(&input());
+
+        assert!(result.is_err());
+    }
+}
+
+mod prolog {
+    use super::*;
+
+    use crate::{IllegalCharacters, SerializerBuilder};
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "OFX", rename_all = "UPPERCASE")]
+    struct Ofx {
+        signon: String,
+    }
+
+    fn input() -> Ofx {
+        Ofx { signon: "plain text".to_string() }
+    }
+
+    #[test]
+    fn custom_header() {
+        setup();
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <?OFX VERSION="203"?>
+            <OFX>
+              <SIGNON>plain text</SIGNON>
+            </OFX>"#);
+
+        let actual = SerializerBuilder::new()
+            .processing_instruction("OFX", Some(r#"VERSION="203""#))
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn all_items() {
+        setup();
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8" standalone="no"?>
+            <!-- generated -->
+            <!DOCTYPE OFX SYSTEM "ofx.dtd">
+            <?xml-stylesheet href="ofx.css"?>
+            <OFX>
+              <SIGNON>plain text</SIGNON>
+            </OFX>"#);
+
+        let actual = SerializerBuilder::new()
+            .standalone(false)
+            .comment("generated")
+            .doctype("OFX SYSTEM \"ofx.dtd\"")
+            .processing_instruction("xml-stylesheet", Some(r#"href="ofx.css""#))
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn without_declaration() {
+        setup();
+
+        let expected = indoc!(r#"
+            <!DOCTYPE OFX>
+            <!-- generated -->
+            <OFX>
+              <SIGNON>plain text</SIGNON>
+            </OFX>"#);
+
+        let actual = SerializerBuilder::new()
+            .xml_declaration(false)
+            .doctype("OFX SYSTEM \"ofx.dtd\"")
+            .comment("generated")
+            .doctype("OFX")
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn compact() {
+        setup();
+
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE OFX><?OFX?><OFX><SIGNON>plain text</SIGNON></OFX>"#;
+
+        let actual = SerializerBuilder::compact()
+            .doctype("OFX")
+            .processing_instruction("OFX", None)
+            .to_string(&input())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn invalid() {
+        setup();
+
+        assert!(SerializerBuilder::new().comment("a -- b").to_string(&input()).is_err());
+        assert!(SerializerBuilder::new().processing_instruction("xml", None).to_string(&input()).is_err());
+        assert!(SerializerBuilder::new().processing_instruction("pi", Some("?>")).to_string(&input()).is_err());
+    }
+
+    #[test]
+    fn invalid_doctype() {
+        setup();
+
+        let valid = r#"OFX SYSTEM "ofx.dtd" [<!ENTITY gt2 ">>">]"#;
+        assert!(SerializerBuilder::new().doctype(valid).to_string(&input()).is_ok());
+
+        for doctype in &["", " OFX", "OFX><injected/", "OFX [ ]><injected/><!DOCTYPE OFX", "OFX [", "OFX 'ofx.dtd"] {
+            let err = SerializerBuilder::new().doctype(*doctype).to_string(&input()).unwrap_err();
+            assert!(err.to_string().contains("invalid prolog item"), "{:?}: {}", doctype, err);
+        }
+    }
+
+    #[test]
+    fn illegal_characters() {
+        setup();
+
+        let builder = SerializerBuilder::compact()
+            .xml_declaration(false)
+            .comment("gene\u{1}rated")
+            .processing_instruction("OFX", Some("VERSION=\u{1}"));
+        assert!(builder.clone().to_string(&input()).is_err());
+
+        let expected = "<!-- gene\u{FFFD}rated --><?OFX VERSION=\u{FFFD}?><OFX><SIGNON>plain text</SIGNON></OFX>";
+        let actual = builder.illegal_characters(IllegalCharacters::Replace).to_string(&input()).unwrap();
+        assert_eq!(expected, actual);
+    }
+}
+
+mod cdata {
+    use super::*;
+
+    use crate::{to_string_compact, CData};
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "page")]
+    struct Page {
+        #[serde(rename = "@title")]
+        title: CData<String>,
+        body: CData<String>,
+        #[serde(serialize_with = "crate::cdata::serialize")]
+        script: String,
+        count: CData<u32>,
+    }
+
+    #[test]
+    fn wrapper_and_helper() {
+        setup();
+
+        let input = Page {
+            title: CData("<Home>".to_string()),
+            body: CData("<p>Fish & chips</p>".to_string()),
+            script: "if (a < b) {}".to_string(),
+            count: CData(3),
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <page title="&lt;Home&gt;">
+              <body><![CDATA[<p>Fish & chips</p>]]></body>
+              <script><![CDATA[if (a < b) {}]]></script>
+              <count><![CDATA[3]]></count>
+            </page>"#);
+
+        assert_eq!(expected, to_string(&input).unwrap());
+    }
+
+    #[test]
+    fn split_terminator() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "document")]
+        struct Document {
+            value: CData<String>,
+        }
+
+        let input = Document { value: CData("a]]>b]]>".to_string()) };
+
+        let expected = concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<document><value><![CDATA[a]]]]><![CDATA[>b]]]]><![CDATA[>]]></value></document>"#);
+
+        assert_eq!(expected, to_string_compact(&input).unwrap());
+    }
+}
+
+mod comment {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "config")]
+    struct Config {
+        #[serde(rename = "#comment")]
+        comment: Option<String>,
+        server: Server,
+    }
+
+    #[derive(Debug, PartialEq, Serialize)]
+    struct Server {
+        #[serde(rename = "@name")]
+        name: String,
+        #[serde(rename = "#comment")]
+        comment: String,
+        port: u16,
+    }
+
+    #[test]
+    fn before_elements() {
+        setup();
+
+        let input = Config {
+            comment: Some("Generated, do not edit".to_string()),
+            server: Server { name: "main".to_string(), comment: "Between 1024 and 65535".to_string(), port: 8080 },
+        };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <config>
+              <!-- Generated, do not edit -->
+              <server name="main">
+                <!-- Between 1024 and 65535 -->
+                <port>8080</port>
+              </server>
+            </config>"#);
+
+        assert_eq!(expected, to_string(&input).unwrap());
+    }
+
+    #[test]
+    fn absent() {
+        setup();
+
+        let input = Config {
+            comment: None,
+            server: Server { name: "main".to_string(), comment: "port".to_string(), port: 8080 },
+        };
+
+        assert!(!to_string(&input).unwrap().contains("Generated"));
+    }
+
+    #[test]
+    fn invalid() {
+        setup();
+
+        let input = Config {
+            comment: Some("a -- b".to_string()),
+            server: Server { name: "main".to_string(), comment: "port".to_string(), port: 8080 },
+        };
+
+        assert!(to_string(&input).is_err());
+    }
+
+    #[test]
+    fn several() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "server")]
+        struct Annotated {
+            #[serde(rename = "#comment")]
+            comments: Vec<String>,
+            port: u16,
+        }
+
+        let input = Annotated { comments: vec!["Primary".to_string(), "Port".to_string()], port: 8080 };
+
+        let expected = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <server>
+              <!-- Primary -->
+              <!-- Port -->
+              <port>8080</port>
+            </server>"#);
+
+        assert_eq!(expected, to_string(&input).unwrap());
+    }
+
+    #[test]
+    fn not_text() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "server")]
+        struct Annotated {
+            #[serde(rename = "#comment")]
+            comments: Vec<Vec<String>>,
+        }
+
+        let input = Annotated { comments: vec![vec!["Primary".to_string()]] };
+
+        assert_eq!("a sequence cannot be written as text", to_string(&input).unwrap_err().to_string());
+    }
+}
+
+mod map_entries {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::{MapRepresentation, SerializerBuilder};
+
+    #[derive(Debug, PartialEq, Serialize)]
+    #[serde(rename = "report")]
+    struct Report<K: Ord> {
+        totals: BTreeMap<K, u32>,
+    }
+
+    fn report() -> Report<String> {
+        let mut totals = BTreeMap::new();
+        totals.insert("2024 Q1".to_string(), 10);
+        totals.insert("a/b".to_string(), 20);
+        Report { totals }
+    }
+
+    #[test]
+    fn invalid_element_name() {
+        setup();
+
+        assert!(to_string(&report()).is_err());
+
+        let mut totals = BTreeMap::new();
+        totals.insert(1, 10);
+        assert!(to_string(&Report { totals }).is_err());
+    }
+
+    #[test]
+    fn entries() {
+        setup();
+
+        let expected = r#"<report><totals><entry key="2024 Q1">10</entry><entry key="a/b">20</entry></totals></report>"#;
+
+        let actual = SerializerBuilder::compact()
+            .xml_declaration(false)
+            .map_representation(MapRepresentation::entries())
+            .to_string(&report())
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn custom_names() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize)]
+        struct Total {
+            #[serde(rename = "@currency")]
+            currency: String,
+            #[serde(rename = ".")]
+            amount: u32,
+        }
+
+        #[derive(Debug, PartialEq, Serialize)]
+        #[serde(rename = "report")]
+        struct Report {
+            totals: BTreeMap<u16, Total>,
+        }
+
+        let mut totals = BTreeMap::new();
+        totals.insert(2024, Total { currency: "EUR".to_string(), amount: 10 });
+
+        let expected = r#"<report><totals><total year="2024" currency="EUR">10</total></totals></report>"#;
+
+        let actual = SerializerBuilder::compact()
+            .xml_declaration(false)
+            .map_representation(MapRepresentation::Entries { element: "total".to_string(), key: "year".to_string() })
+            .to_string(&Report { totals })
+            .unwrap();
+
+        assert_eq!(expected, actual);
+    }
+}
+
+mod names {
+    use super::*;
+
+    fn error_message<S: Serialize>(value: &S) -> String {
+        to_string(value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn field() {
+        setup();
+
+        #[derive(Serialize)]
+        struct Document {
+            #[serde(rename = "1st")]
+            first: u32,
+        }
+
+        assert!(error_message(&Document { first: 1 }).contains("\"1st\""));
+    }
+
+    #[test]
+    fn renamed_root() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "my document")]
+        struct Document {
+            value: u32,
+        }
+
+        assert!(error_message(&Document { value: 1 }).contains("\"my document\""));
+    }
+
+    #[test]
+    fn variant() {
+        setup();
+
+        #[derive(Serialize)]
+        enum Kind {
+            #[serde(rename = "a/b")]
+            Slash(u32),
+        }
+
+        #[derive(Serialize)]
+        struct Document {
+            kind: Kind,
+        }
+
+        assert!(error_message(&Document { kind: Kind::Slash(1) }).contains("\"a/b\""));
+    }
+
+    #[test]
+    fn attribute() {
+        setup();
+
+        #[derive(Serialize)]
+        struct Document {
+            #[serde(rename = "@bad name")]
+            value: u32,
+        }
+
+        assert!(error_message(&Document { value: 1 }).contains("\"bad name\""));
+    }
+
+    #[test]
+    fn prefixes() {
+        setup();
+
+        #[derive(Serialize)]
+        struct Reserved {
+            #[serde(rename = "xmlns:value")]
+            value: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Colons {
+            #[serde(rename = "a:b:c")]
+            value: u32,
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename = "x:document")]
+        struct Prefixed {
+            #[serde(rename = "@x:id")]
+            id: u32,
+            #[serde(rename = "x:value")]
+            value: u32,
+        }
+
+        assert!(to_string(&Reserved { value: 1 }).is_err());
+        assert!(to_string(&Colons { value: 1 }).is_err());
+        assert!(to_string_ns(&Prefixed { id: 1, value: 2 }, None, &[("x", "urn:x")]).is_ok());
+    }
+}
+
+mod illegal_characters {
+    use super::*;
+    use super::super::{IllegalCharacters, SerializerBuilder};
+
+    #[derive(Serialize)]
+    #[serde(rename = "document")]
+    struct Document {
+        #[serde(rename = "@id")]
+        id: String,
+        value: String,
+    }
+
+    fn document() -> Document {
+        Document { id: "a\u{0}b".to_string(), value: "\u{1B}[1mbold\u{FFFF}".to_string() }
+    }
+
+    fn with_policy(illegal_characters: IllegalCharacters) -> Result<String, error::Error> {
+        SerializerBuilder::compact()
+            .xml_declaration(false)
+            .illegal_characters(illegal_characters)
+            .to_string(&document())
+    }
+
+    #[test]
+    fn error_by_default() {
+        setup();
+
+        let message = to_string(&document()).unwrap_err().to_string();
+        assert!(message.contains("U+0000"), "{}", message);
+
+        let text = Document { id: "a".to_string(), value: "\u{1B}".to_string() };
+        let message = to_string(&text).unwrap_err().to_string();
+        assert!(message.contains("U+001B"), "{}", message);
+    }
+
+    #[test]
+    fn strip() {
+        setup();
+
+        let expected = r#"<document id="ab"><value>[1mbold</value></document>"#;
+        assert_eq!(expected, with_policy(IllegalCharacters::Strip).unwrap());
+    }
+
+    #[test]
+    fn replace() {
+        setup();
+
+        let expected = "<document id=\"a\u{FFFD}b\"><value>\u{FFFD}[1mbold\u{FFFD}</value></document>";
+        assert_eq!(expected, with_policy(IllegalCharacters::Replace).unwrap());
+    }
+
+    #[test]
+    fn allowed_whitespace() {
+        setup();
+
+        let document = Document { id: "a".to_string(), value: "tab\tnew\nline".to_string() };
+        assert!(to_string(&document).unwrap().contains("tab\tnew\nline"));
+    }
+
+    #[test]
+    fn cdata() {
+        setup();
+
+        #[derive(Serialize)]
+        struct Page {
+            body: ::CData<String>,
+        }
+
+        assert!(to_string(&Page { body: ::CData("\u{8}".to_string()) }).is_err());
+    }
+}
+
+mod xml_writer {
+    use super::*;
+    use super::super::{SerializerBuilder, XmlWriter};
+
+    #[derive(Serialize)]
+    #[serde(rename = "record")]
+    struct Record {
+        #[serde(rename = "@id")]
+        id: u32,
+        name: String,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "export")]
+    struct Export {
+        record: Vec<Record>,
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record { id: 1, name: "first".to_string() },
+            Record { id: 2, name: "second".to_string() },
+        ]
+    }
+
+    fn streamed(builder: &SerializerBuilder) -> String {
+        let mut writer = builder.xml_writer(Vec::new()).unwrap();
+        writer.begin_root("export", &[]).unwrap();
+        for record in records() {
+            writer.write_element(&record).unwrap();
+        }
+        String::from_utf8(writer.end().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn same_as_to_string() {
+        setup();
+
+        let builder = SerializerBuilder::new();
+        let expected = builder.to_string(&Export { record: records() }).unwrap();
+        assert_eq!(expected, streamed(&builder));
+
+        let builder = SerializerBuilder::compact();
+        let expected = builder.to_string(&Export { record: records() }).unwrap();
+        assert_eq!(expected, streamed(&builder));
+    }
+
+    #[test]
+    fn root_namespaces() {
+        setup();
+
+        #[derive(Serialize)]
+        #[serde(rename = "x:item")]
+        struct Item {
+            value: u32,
+        }
+
+        let builder = SerializerBuilder::compact()
+            .xml_declaration(false)
+            .default_namespace("urn:export")
+            .namespace("x", "urn:x");
+        let mut writer = builder.xml_writer(Vec::new()).unwrap();
+        writer.begin_root("export", &[("count", "2")]).unwrap();
+        writer.write_element(&Item { value: 1 }).unwrap();
+        writer.write_element(&Item { value: 2 }).unwrap();
+        let actual = String::from_utf8(writer.end().unwrap().into_inner()).unwrap();
+
+        let expected = concat!(
+            r#"<export xmlns="urn:export" xmlns:x="urn:x" count="2">"#,
+            "<x:item><value>1</value></x:item>",
+            "<x:item><value>2</value></x:item>",
+            "</export>");
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn named_element() {
+        setup();
+
+        let ser = Serializer::new_from_writer(
+            EmitterConfig::new().write_document_declaration(false).create_writer(Vec::new()),
+            None, &[]);
+        let mut writer = XmlWriter::new(ser);
+        writer.begin_root("values", &[]).unwrap();
+        writer.write_named_element("value", &1).unwrap();
+        writer.write_named_element("entry", &Record { id: 3, name: "third".to_string() }).unwrap();
+        let actual = String::from_utf8(writer.end().unwrap()).unwrap();
+
+        assert_eq!(r#"<values><value>1</value><entry id="3"><name>third</name></entry></values>"#, actual);
+    }
+
+    /// Keeps what was written by the time of each flush.
+    #[derive(Default)]
+    struct Connection {
+        written: Vec<u8>,
+        flushed: Vec<String>,
+    }
+
+    impl ::std::io::Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            self.flushed.push(String::from_utf8(self.written.clone()).unwrap());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn auto_flush() {
+        setup();
+
+        let builder = SerializerBuilder::compact().xml_declaration(false);
+        let mut writer = builder.xml_writer(Connection::default()).unwrap().with_auto_flush(true);
+        writer.begin_root("stream:stream", &[("to", "example.com")]).unwrap();
+        writer.write_element(&records()[0]).unwrap();
+        writer.write_named_element("presence", &()).unwrap();
+        let connection = writer.end().unwrap().into_inner();
+
+        let header = r#"<stream:stream to="example.com">"#;
+        let record = r#"<record id="1"><name>first</name></record>"#;
+        let expected = vec![
+            header.to_string(),
+            format!("{}{}", header, record),
+            format!("{}{}<presence />", header, record),
+            format!("{}{}<presence /></stream:stream>", header, record),
+        ];
+        assert_eq!(expected, connection.flushed);
+    }
+
+    #[test]
+    fn auto_flush_indent() {
+        setup();
+
+        let builder = SerializerBuilder::new();
+        let mut writer = builder.xml_writer(Vec::new()).unwrap().with_auto_flush(true);
+        writer.begin_root("export", &[]).unwrap();
+        writer.flush().unwrap();
+        for record in records() {
+            writer.write_element(&record).unwrap();
+        }
+        let actual = String::from_utf8(writer.end().unwrap().into_inner()).unwrap();
+
+        assert_eq!(builder.to_string(&Export { record: records() }).unwrap(), actual);
+    }
+
+    #[test]
+    fn flush_adds_no_text() {
+        setup();
+
+        let mut writer = SerializerBuilder::new().xml_declaration(false).xml_writer(Vec::new()).unwrap();
+        writer.begin_root("export", &[]).unwrap();
+        writer.flush().unwrap();
+        let actual = String::from_utf8(writer.end().unwrap().into_inner()).unwrap();
+
+        assert_eq!("<export></export>", actual);
+    }
+
+    #[test]
+    fn order() {
+        setup();
+
+        let mut writer = SerializerBuilder::new().xml_writer(Vec::new()).unwrap();
+        assert!(writer.write_element(&records()[0]).is_err());
+        writer.begin_root("export", &[]).unwrap();
+        assert!(writer.begin_root("export", &[]).is_err());
+
+        let mut writer = SerializerBuilder::new().xml_writer(Vec::new()).unwrap();
+        assert!(writer.begin_root("bad name", &[]).is_err());
+        assert!(writer.end().is_err());
+    }
 }
 
 mod ns {
@@ -737,7 +2264,7 @@ mod ns {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document xmlns="urn:example:document">
               <content>abc 123</content>
             </document>"#);
@@ -763,7 +2290,7 @@ mod ns {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document xmlns:content="urn:example:content">
               <content:content>abc 123</content:content>
             </document>"#);
@@ -789,7 +2316,7 @@ mod ns {
         };
 
         let expected = indoc!(r#"
-            <?xml version="1.0" encoding="utf-8"?>
+            <?xml version="1.0" encoding="UTF-8"?>
             <document xmlns:content="urn:example:content" content:content="abc 123" />"#);
 
         let actual = to_string_ns(&input, None, &[("content", "urn:example:content")]).unwrap();
@@ -0,0 +1,150 @@
+use std::io::Write;
+use std::mem;
+
+use serde::ser::Serialize;
+
+use super::error::{self, Result};
+use super::Serializer;
+
+/// Writes a document one child of the root element at a time, for output
+/// too large to hold in memory.
+///
+/// Namespaces given to the serializer are declared on the root element, and
+/// the elements are indented as `to_writer` would indent them inside it.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # extern crate serde_xml;
+/// # use serde_xml::SerializerBuilder;
+/// #[derive(Serialize)]
+/// #[serde(rename = "record")]
+/// struct Record {
+///     id: u32,
+/// }
+///
+/// # fn main() {
+/// let mut writer = SerializerBuilder::compact()
+///     .xml_declaration(false)
+///     .xml_writer(Vec::new())
+///     .unwrap();
+/// writer.begin_root("export", &[("version", "2")]).unwrap();
+/// for id in 0..2 {
+///     writer.write_element(&Record { id }).unwrap();
+/// }
+/// let output = writer.end().unwrap().into_inner();
+/// assert_eq!(
+///     r#"<export version="2"><record><id>0</id></record><record><id>1</id></record></export>"#,
+///     String::from_utf8(output).unwrap());
+/// # }
+/// ```
+pub struct XmlWriter<W: Write> {
+    ser: Serializer<W>,
+    started: bool,
+    auto_flush: bool,
+    /// The root start tag has been written without its `>`.
+    pending_root: bool,
+    /// The root start tag was closed by `flush`, after which the emitter
+    /// does not indent the first element.
+    unindented: bool,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(ser: Serializer<W>) -> Self {
+        XmlWriter { ser, started: false, auto_flush: false, pending_root: false, unindented: false }
+    }
+
+    /// Flushes the output after the root start tag and after each element, so
+    /// that the other end of a stream, as in XMPP, gets them right away.
+    pub fn with_auto_flush(mut self, auto_flush: bool) -> Self {
+        self.auto_flush = auto_flush;
+        self
+    }
+
+    /// Writes the start tag of the root element.
+    pub fn begin_root(&mut self, name: &str, attrs: &[(&str, &str)]) -> Result<()> {
+        if self.started {
+            return Err(error::with_message("the root element has already been written".into()));
+        }
+        let attrs = attrs.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.ser.start_tag(name, attrs)?;
+        self.ser.root = false;
+        self.started = true;
+        self.pending_root = true;
+        self.flushed()
+    }
+
+    /// Writes a value inside the root element, as an element named after its
+    /// type.
+    pub fn write_element<S: Serialize>(&mut self, value: &S) -> Result<()> {
+        self.check_started()?;
+        self.indent()?;
+        self.ser.root = true;
+        let result = self.ser.element(value);
+        self.ser.root = false;
+        result?;
+        self.pending_root = false;
+        self.flushed()
+    }
+
+    /// Writes a value inside the root element, as an element named `name`.
+    pub fn write_named_element<S: Serialize>(&mut self, name: &str, value: &S) -> Result<()> {
+        self.check_started()?;
+        self.indent()?;
+        self.ser.open_tag(name)?;
+        self.ser.element(value)?;
+        self.pending_root = false;
+        self.flushed()
+    }
+
+    /// Writes out everything written so far, including the root start tag.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.pending_root {
+            // The emitter only closes a start tag once it knows whether the
+            // element is empty, which empty text tells it
+            self.ser.characters("")?;
+            self.pending_root = false;
+            self.unindented = true;
+        }
+        self.ser.flush()
+    }
+
+    /// Writes the end tag of the root element and returns the underlying
+    /// writer.
+    pub fn end(mut self) -> Result<W> {
+        self.check_started()?;
+        self.ser.end_tag()?;
+        let mut writer = self.ser.into_inner();
+        writer.flush().map_err(error::io)?;
+        Ok(writer)
+    }
+
+    /// Writes the indentation the emitter leaves out after text.
+    fn indent(&mut self) -> Result<()> {
+        if !mem::replace(&mut self.unindented, false) {
+            return Ok(());
+        }
+        match self.ser.line_separator {
+            Some(ref line_separator) => write!(self.ser.writer.inner_mut(), "{}{}", line_separator, self.ser.indent_string)
+                .map_err(error::io),
+            None => Ok(()),
+        }
+    }
+
+    fn flushed(&mut self) -> Result<()> {
+        if self.auto_flush {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_started(&self) -> Result<()> {
+        if self.started {
+            Ok(())
+        } else {
+            Err(error::with_message("the root element has not been written".into()))
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::future::{self, Future};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use futures_core::Stream;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use ::async_io::{from_async_reader, to_async_writer, RecordStream, WRITE_BUFFER_SIZE};
+use ::lexical::LexicalForm;
+use ::limits::{Limit, Limits};
+use ::ser::to_string;
+
+pub use super::setup_logger;
+
+fn setup() {
+    setup_logger();
+}
+
+/// Wakes the thread polling a future.
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Hands out a few bytes at a time, and is not ready every other time.
+struct Trickle {
+    data: Vec<u8>,
+    position: usize,
+    ready: bool,
+}
+
+impl Trickle {
+    fn new(data: &[u8]) -> Self {
+        Trickle { data: data.to_vec(), position: 0, ready: false }
+    }
+}
+
+impl AsyncRead for Trickle {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.ready = false;
+        let end = (self.position + 3).min(self.data.len());
+        buf.put_slice(&self.data[self.position..end]);
+        self.position = end;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "record")]
+struct Record {
+    #[serde(rename = "@id")]
+    id: u32,
+    name: String,
+}
+
+fn records<R: AsyncRead + Unpin>(reader: R) -> Vec<::Result<Option<Record>>> {
+    let mut stream = RecordStream::new(reader);
+    let mut records = Vec::new();
+    loop {
+        let record = block_on(stream.next_record());
+        let last = !matches!(record, Ok(Some(_)));
+        records.push(record);
+        if last {
+            return records;
+        }
+    }
+}
+
+#[test]
+fn read_document() {
+    setup();
+
+    let input = br#"<?xml version="1.0"?><record id="1"><name>first</name></record>"#;
+    let record: Record = block_on(from_async_reader(Trickle::new(input))).unwrap();
+
+    assert_eq!(Record { id: 1, name: "first".to_string() }, record);
+}
+
+#[test]
+fn write_document() {
+    setup();
+
+    let record = Record { id: 1, name: "first".to_string() };
+    let mut output = Vec::new();
+    block_on(to_async_writer(&mut output, &record)).unwrap();
+
+    assert_eq!(to_string(&record).unwrap(), String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn write_error() {
+    setup();
+
+    let record = Record { id: 1, name: "\u{0}".to_string() };
+    assert!(block_on(to_async_writer(Vec::new(), &record)).is_err());
+}
+
+#[test]
+fn record_stream() {
+    setup();
+
+    let input = indoc!(r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <!-- exported -->
+        <export count="3">
+          <record id="1"><name>first</name></record>
+          <!-- <record id="0"> -->
+          <record id="2">
+            <name><![CDATA[</record>]]></name>
+          </record>
+          <?page 2?>
+          <record id='3' ><name>a &gt; b</name></record>
+        </export>"#);
+
+    let actual: Vec<Record> = records(Trickle::new(input.as_bytes())).into_iter()
+        .map(|record| record.unwrap())
+        .take_while(Option::is_some)
+        .map(Option::unwrap)
+        .collect();
+
+    let expected = vec![
+        Record { id: 1, name: "first".to_string() },
+        Record { id: 2, name: "</record>".to_string() },
+        Record { id: 3, name: "a > b".to_string() },
+    ];
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn record_stream_namespaces() {
+    setup();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Item {
+        #[serde(rename = "x:value")]
+        value: u32,
+    }
+
+    let input = br#"<export xmlns:x="urn:x"><x:item><x:value>1</x:value></x:item><x:item xmlns:x="urn:y"><x:value>2</x:value></x:item></export>"#;
+    let mut stream = RecordStream::new(Trickle::new(input));
+
+    let first: Option<Item> = block_on(stream.next_record()).unwrap();
+    let second: Option<Item> = block_on(stream.next_record()).unwrap();
+    assert_eq!(Some(Item { value: 1 }), first);
+    assert_eq!(Some(Item { value: 2 }), second);
+    assert_eq!(None, block_on(stream.next_record()).unwrap());
+    assert_eq!(None, block_on(stream.next_record()).unwrap());
+}
+
+#[test]
+fn record_stream_encoding() {
+    setup();
+
+    let input = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><export><record id=\"1\"><name>caf\xE9</name></record></export>";
+    let records = records(&input[..]);
+
+    assert_eq!(Some(Record { id: 1, name: "caf\u{E9}".to_string() }), *records[0].as_ref().unwrap());
+}
+
+#[test]
+fn record_stream_truncated() {
+    setup();
+
+    let records = records(Trickle::new(br#"<export><record id="1"><name>first</name></record><record id="2"><na"#));
+
+    assert_eq!(2, records.len());
+    assert!(records[0].is_ok());
+    assert!(records[1].is_err());
+}
+
+#[test]
+fn record_stream_limits() {
+    setup();
+
+    let input = format!(r#"<export><record id="1"><name>{}</name></record></export>"#, "a".repeat(10_000));
+    let limits = Limits { max_input_bytes: 1_000, ..Limits::default() };
+    let mut stream = RecordStream::<_, Record>::new(Trickle::new(input.as_bytes())).with_limits(limits);
+
+    let err = block_on(stream.next_record()).unwrap_err();
+    assert_eq!(Some(Limit::InputBytes), err.limit());
+}
+
+#[test]
+fn record_stream_as_stream() {
+    setup();
+
+    let input = br#"<export><record id="1"><name>first</name></record><record id="x"><name/></record>
+        <record id="3"><name>third</name></record></export>"#;
+    let mut stream = RecordStream::<_, Record>::new(Trickle::new(input));
+    let mut records = Vec::new();
+    while let Some(record) = block_on(future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx))) {
+        records.push(record);
+    }
+
+    assert_eq!(3, records.len());
+    assert_eq!(Record { id: 1, name: "first".to_string() }, *records[0].as_ref().unwrap());
+    assert!(records[1].is_err());
+    assert_eq!(Record { id: 3, name: "third".to_string() }, *records[2].as_ref().unwrap());
+}
+
+#[test]
+fn read_document_limits() {
+    setup();
+
+    let input = format!(r#"<record id="1"><name>{}</name></record>"#, "a".repeat(100_000));
+    let limits = Limits { max_input_bytes: 50_000, ..Limits::default() };
+    let err = block_on(from_async_reader::<_, Record>(input.as_bytes()).with_limits(limits)).unwrap_err();
+
+    assert_eq!(Some(Limit::InputBytes), err.limit());
+}
+
+#[test]
+fn read_document_lexical_form() {
+    setup();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Flag {
+        value: bool,
+    }
+
+    let input = b"<flag><value>1</value></flag>";
+    let flag: Flag = block_on(from_async_reader(Trickle::new(input))).unwrap();
+    assert_eq!(Flag { value: true }, flag);
+
+    let read = from_async_reader::<_, Flag>(Trickle::new(input)).with_lexical_form(LexicalForm::Rust);
+    assert!(block_on(read).is_err());
+}
+
+/// Takes a few bytes at a time, and is not ready for a long while every so
+/// often, keeping what it was given.
+struct Stalling {
+    output: Vec<u8>,
+    polls: usize,
+}
+
+impl AsyncWrite for Stalling {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.polls += 1;
+        if self.polls % 500 < 100 {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let n = buf.len().min(1000);
+        self.output.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[test]
+fn write_document_stalling() {
+    setup();
+
+    #[derive(Serialize)]
+    struct Export {
+        record: Vec<Record>,
+    }
+
+    let export = Export {
+        record: (0..10_000).map(|id| Record { id, name: format!("record {}", id) }).collect(),
+    };
+    let mut writer = Stalling { output: Vec::new(), polls: 0 };
+    block_on(to_async_writer(&mut writer, &export)).unwrap();
+
+    let expected = to_string(&export).unwrap();
+    assert!(expected.len() > 4 * WRITE_BUFFER_SIZE);
+    assert_eq!(expected, String::from_utf8(writer.output).unwrap());
+}
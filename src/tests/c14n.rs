@@ -0,0 +1,152 @@
+use ::c14n::{canonicalize, Canonicalization};
+use ::ser::SerializerBuilder;
+
+use super::setup_logger;
+
+fn setup() {
+    setup_logger();
+}
+
+const PIS_AND_COMMENTS: &str = r#"<?xml version="1.0"?>
+
+<?xml-stylesheet   href="doc.xsl"
+   type="text/xsl"   ?>
+
+<!DOCTYPE doc SYSTEM "doc.dtd">
+
+<doc>Hello, world!<!-- Comment 1 --></doc>
+
+<?pi-without-data     ?>
+
+<!-- Comment 2 -->
+
+<!-- Comment 3 -->"#;
+
+#[test]
+fn pis_and_comments() {
+    setup();
+
+    let expected = concat!(
+        "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n",
+        "<doc>Hello, world!</doc>\n",
+        "<?pi-without-data?>");
+
+    assert_eq!(expected, canonicalize(PIS_AND_COMMENTS, Canonicalization::C14n).unwrap());
+}
+
+#[test]
+fn pis_and_comments_with_comments() {
+    setup();
+
+    let expected = concat!(
+        "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n",
+        "<doc>Hello, world!<!-- Comment 1 --></doc>\n",
+        "<?pi-without-data?>\n",
+        "<!-- Comment 2 -->\n",
+        "<!-- Comment 3 -->");
+
+    assert_eq!(expected, canonicalize(PIS_AND_COMMENTS, Canonicalization::C14nWithComments).unwrap());
+}
+
+#[test]
+fn whitespace_in_content() {
+    setup();
+
+    let input = "<doc>\r\n   <clean>   </clean>\r\n   <dirty>   A   B   </dirty>\r\n   <mixed>\r\n      A\r\n      <clean>   </clean>\r\n   </mixed>\r\n</doc>";
+
+    let expected = "<doc>\n   <clean>   </clean>\n   <dirty>   A   B   </dirty>\n   <mixed>\n      A\n      <clean>   </clean>\n   </mixed>\n</doc>";
+
+    assert_eq!(expected, canonicalize(input, Canonicalization::C14n).unwrap());
+}
+
+#[test]
+fn start_and_end_tags() {
+    setup();
+
+    let input = r#"<doc>
+   <e1   />
+   <e2   ></e2>
+   <e3   name = "elem3"   id="elem3"   />
+   <e5 a:attr="out" b:attr="sorted" attr2="all" attr="I'm"
+      xmlns:b="http://www.ietf.org"
+      xmlns:a="http://www.w3.org"
+      xmlns="http://example.org"/>
+   <e6 xmlns="" xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="" xmlns:a="http://www.w3.org">
+            <e9 xmlns="" xmlns:a="http://www.ietf.org"/>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+
+    let expected = r#"<doc>
+   <e1></e1>
+   <e2></e2>
+   <e3 id="elem3" name="elem3"></e3>
+   <e5 xmlns="http://example.org" xmlns:a="http://www.w3.org" xmlns:b="http://www.ietf.org" attr="I'm" attr2="all" b:attr="sorted" a:attr="out"></e5>
+   <e6 xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="">
+            <e9 xmlns:a="http://www.ietf.org"></e9>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+
+    assert_eq!(expected, canonicalize(input, Canonicalization::C14n).unwrap());
+}
+
+#[test]
+fn character_modifications() {
+    setup();
+
+    let input = "<doc>\n   <text>First line&#x0d;&#10;Second line</text>\n   <value>&#x32;</value>\n   <compute><![CDATA[value>\"0\" && value<\"10\" ?\"valid\":\"error\"]]></compute>\n   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>\n</doc>";
+
+    let expected = "<doc>\n   <text>First line&#xD;\nSecond line</text>\n   <value>2</value>\n   <compute>value&gt;\"0\" &amp;&amp; value&lt;\"10\" ?\"valid\":\"error\"</compute>\n   <norm attr=\" '    &#xD;&#xA;&#x9;   ' \"></norm>\n</doc>";
+
+    assert_eq!(expected, canonicalize(input, Canonicalization::C14n).unwrap());
+}
+
+#[test]
+fn exclusive_namespaces() {
+    setup();
+
+    let input = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"/></n1:elem2></n0:local>"#;
+
+    let inclusive = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff></n3:stuff></n1:elem2></n0:local>"#;
+    let exclusive = r#"<n0:local xmlns:n0="foo:bar"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"></n3:stuff></n1:elem2></n0:local>"#;
+
+    assert_eq!(inclusive, canonicalize(input, Canonicalization::C14n).unwrap());
+    assert_eq!(exclusive, canonicalize(input, Canonicalization::ExclusiveC14n).unwrap());
+}
+
+#[test]
+fn serializer() {
+    setup();
+
+    #[derive(Serialize)]
+    #[serde(rename = "payment")]
+    struct Payment {
+        #[serde(rename = "@z")]
+        z: String,
+        #[serde(rename = "@a")]
+        a: String,
+        note: String,
+        empty: (),
+    }
+
+    let input = Payment { z: "1".to_string(), a: "x\ty".to_string(), note: "a > b".to_string(), empty: () };
+
+    let expected = r#"<payment xmlns="urn:example" a="x y" z="1"><note>a &gt; b</note><empty></empty></payment>"#;
+
+    let actual = SerializerBuilder::new()
+        .standalone(true)
+        .comment("dropped")
+        .default_namespace("urn:example")
+        .canonical(Canonicalization::ExclusiveC14n)
+        .to_string(&input)
+        .unwrap();
+
+    assert_eq!(expected, actual);
+}
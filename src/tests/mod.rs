@@ -1,3 +1,6 @@
+#[cfg(feature = "tokio")]
+mod async_io;
+mod c14n;
 mod round_trip;
 
 pub fn setup_logger() {
@@ -1,3 +1,5 @@
+#![allow(clippy::upper_case_acronyms)]
+
 use std::collections::HashMap;
 use std::fmt::Debug;
 
@@ -12,7 +14,7 @@ fn setup() {
     setup_logger();
 }
 
-fn round_trip<T>(object: &T) -> ()
+fn round_trip<T>(object: &T)
 where
     T: Debug + PartialEq + Serialize + for<'de> Deserialize<'de>
 {
@@ -23,7 +25,7 @@ where
     assert_eq!(object, &actual);
 }
 
-fn round_trip_ns<T>(object: &T, default_ns: Option<&str>, namespaces: &[(&str, &str)]) -> ()
+fn round_trip_ns<T>(object: &T, default_ns: Option<&str>, namespaces: &[(&str, &str)])
 where
     T: Debug + PartialEq + Serialize + for<'de> Deserialize<'de>
 {
@@ -96,7 +98,7 @@ fn map() {
     #[serde(rename = "document", rename_all = "kebab-case")]
     struct Document {
         content: HashMap<String, String>
-    };
+    }
 
     let object = Document {
         content: [
@@ -241,6 +243,35 @@ fn tuple_variant() {
     round_trip(&object);
 }
 
+#[test]
+fn root_variant() {
+    setup();
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message {
+        #[serde(rename = "@to")]
+        to: String,
+        body: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    enum Stanza {
+        Message(Message),
+        Ping { #[serde(rename = "@id")] id: u32, from: String },
+        Kv(String, String),
+        Presence,
+    }
+
+    let message = Stanza::Message(Message { to: "juliet".to_string(), body: "Wherefore".to_string() });
+    assert!(to_string(&message).unwrap().contains("\n<message to=\"juliet\">"));
+
+    round_trip(&message);
+    round_trip(&Stanza::Ping { id: 7, from: "romeo".to_string() });
+    round_trip(&Stanza::Kv("abc".to_string(), "123".to_string()));
+    round_trip(&Stanza::Presence);
+}
+
 #[test]
 fn tuple_struct() {
     setup();
@@ -515,6 +546,412 @@ mod attribute {
 
         round_trip(&object);
     }
+
+    #[test]
+    fn after_children() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Entity {
+            name: String,
+            #[serde(rename = "@id")]
+            id: String,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            content: Entity,
+            #[serde(rename = "@version")]
+            version: String,
+        }
+
+        let object = Document {
+            content: Entity { name: "abc".to_string(), id: "123".to_string() },
+            version: "1.2.3".to_string(),
+        };
+
+        round_trip(&object);
+    }
+
+    #[test]
+    fn optional() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Entity {
+            #[serde(rename = "@id")]
+            id: Option<String>,
+            #[serde(rename = "@count")]
+            count: Option<u32>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            first: Entity,
+            second: Entity,
+        }
+
+        let object = Document {
+            first: Entity { id: Some("123".to_string()), count: None },
+            second: Entity { id: None, count: Some(42) },
+        };
+
+        round_trip(&object);
+    }
+
+    #[test]
+    fn typed_values() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        enum Status {
+            Active,
+            #[serde(rename = "on-hold")]
+            OnHold,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct OrderId(u32);
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@status")]
+            status: Status,
+            #[serde(rename = "@id")]
+            id: OrderId,
+            #[serde(rename = "@grade")]
+            grade: char,
+            axes: (Status, OrderId, char),
+        }
+
+        let object = Document {
+            status: Status::OnHold,
+            id: OrderId(42),
+            grade: 'B',
+            axes: (Status::Active, OrderId(7), 'x'),
+        };
+
+        round_trip(&object);
+    }
+}
+
+mod list {
+    use super::*;
+
+    use crate::List;
+
+    #[test]
+    fn attribute_and_element() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@coords", with = "crate::list")]
+            coords: Vec<f64>,
+            #[serde(rename = "@empty", with = "crate::list")]
+            empty: Vec<i32>,
+            tags: List<String>,
+            sizes: List<u32>,
+        }
+
+        let object = Document {
+            coords: vec![1.5, 2.0, 3.25],
+            empty: vec![],
+            tags: List(vec!["red".to_string(), "green".to_string(), "blue".to_string()]),
+            sizes: List(vec![]),
+        };
+
+        round_trip(&object);
+    }
+}
+
+mod bytes {
+    use super::*;
+
+    use serde_bytes::ByteBuf;
+
+    #[test]
+    fn attribute_and_element() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@hash", with = "serde_bytes")]
+            hash: Vec<u8>,
+            attachment: ByteBuf,
+            empty: ByteBuf,
+        }
+
+        let object = Document {
+            hash: (0..=255).collect(),
+            attachment: ByteBuf::from(b"Hello, world!".to_vec()),
+            empty: ByteBuf::new(),
+        };
+
+        round_trip(&object);
+    }
+}
+
+mod lexical {
+    use super::*;
+
+    #[test]
+    fn special_floats() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "document", rename_all = "kebab-case")]
+        struct Document {
+            #[serde(rename = "@high")]
+            high: f64,
+            low: f32,
+            values: (f64, f32, bool),
+        }
+
+        let object = Document {
+            high: f64::INFINITY,
+            low: f32::NEG_INFINITY,
+            values: (-0.5, 1.1, false),
+        };
+
+        round_trip(&object);
+    }
+}
+
+mod encoding {
+    use super::*;
+
+    use ::de::from_reader;
+    use ::ser::SerializerBuilder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Payment {
+        #[serde(rename = "@currency")]
+        currency: String,
+        payee: String,
+    }
+
+    #[test]
+    fn latin1() {
+        setup();
+
+        let object = Payment { currency: "€".to_string(), payee: "Zoë 東".to_string() };
+
+        let bytes = SerializerBuilder::new().encoding("ISO-8859-1").to_vec(&object).unwrap();
+        let actual: Payment = from_reader(&bytes[..]).unwrap();
+
+        assert_eq!(object, actual);
+    }
+
+    #[test]
+    fn latin1_markup() {
+        setup();
+
+        let object = Payment { currency: "<€>".to_string(), payee: "é€<".to_string() };
+
+        let bytes = SerializerBuilder::compact().encoding("ISO-8859-1").to_vec(&object).unwrap();
+        let actual: Payment = from_reader(&bytes[..]).unwrap();
+
+        assert_eq!(object, actual);
+    }
+
+    #[test]
+    fn windows_1252() {
+        setup();
+
+        let object = Payment { currency: "€".to_string(), payee: "Zoë & 東".to_string() };
+
+        let bytes = SerializerBuilder::new().encoding("windows-1252").to_vec(&object).unwrap();
+        assert!(bytes.contains(&0x80));
+        let actual: Payment = from_reader(&bytes[..]).unwrap();
+
+        assert_eq!(object, actual);
+    }
+}
+
+mod prolog {
+    use super::*;
+
+    use ::de::from_str_with_prolog;
+    use ::prolog::Prolog;
+    use ::ser::SerializerBuilder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "OFX")]
+    struct Document {
+        value: String,
+    }
+
+    #[test]
+    fn prolog() {
+        setup();
+
+        let input = indoc!(r#"
+            <?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+            <?OFX OFXHEADER="200" VERSION="203"?>
+            <!DOCTYPE OFX SYSTEM "ofx.dtd">
+            <OFX><value>plain text</value></OFX>"#);
+
+        let (prolog, object): (Prolog, Document) = from_str_with_prolog(input).unwrap();
+
+        let output = SerializerBuilder::new().prolog(&prolog).to_string(&object).unwrap();
+        debug!("output: {}", output);
+        let (actual_prolog, actual): (Prolog, Document) = from_str_with_prolog(&output).unwrap();
+
+        assert_eq!(prolog, actual_prolog);
+        assert_eq!(object, actual);
+    }
+}
+
+mod cdata {
+    use super::*;
+
+    use ::cdata::CData;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Page {
+        body: CData<String>,
+        #[serde(serialize_with = "::cdata::serialize")]
+        script: String,
+    }
+
+    #[test]
+    fn cdata() {
+        setup();
+
+        round_trip(&Page {
+            body: CData("<p>]]></p>".to_string()),
+            script: "a ]] > && b".to_string(),
+        });
+    }
+}
+
+mod illegal_characters {
+    use super::*;
+
+    use ::ser::{IllegalCharacters, SerializerBuilder};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "document")]
+    struct Document {
+        #[serde(rename = "@id")]
+        id: String,
+        value: String,
+    }
+
+    #[test]
+    fn replaced() {
+        setup();
+
+        let object = Document { id: "\u{7}".to_string(), value: "a\u{0}b".to_string() };
+        let xml = SerializerBuilder::new()
+            .illegal_characters(IllegalCharacters::Replace)
+            .to_string(&object)
+            .unwrap();
+        let actual: Document = from_str(&xml).unwrap();
+
+        assert_eq!(Document { id: "\u{FFFD}".to_string(), value: "a\u{FFFD}b".to_string() }, actual);
+    }
+}
+
+mod comment {
+    use super::*;
+
+    use ::de::Deserializer;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Server {
+        #[serde(rename = "#comment")]
+        comment: Option<String>,
+        port: u16,
+    }
+
+    #[test]
+    fn comment() {
+        setup();
+
+        let object = Server { comment: Some("Between 1024 and 65535".to_string()), port: 8080 };
+
+        let xml = to_string(&object).unwrap();
+        let mut de = Deserializer::new_from_reader(xml.as_bytes()).unwrap().with_comments(true);
+        let actual = Server::deserialize(&mut de).unwrap();
+
+        assert_eq!(object, actual);
+    }
+
+    #[test]
+    fn several() {
+        setup();
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Annotated {
+            #[serde(rename = "#comment")]
+            comments: Vec<String>,
+            port: u16,
+        }
+
+        let object = Annotated { comments: vec!["Primary".to_string(), "Port".to_string()], port: 8080 };
+
+        let xml = to_string(&object).unwrap();
+        let mut de = Deserializer::new_from_reader(xml.as_bytes()).unwrap().with_comments(true);
+        let actual = Annotated::deserialize(&mut de).unwrap();
+
+        assert_eq!(object, actual);
+    }
+}
+
+mod map_entries {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use ::de::Deserializer;
+    use ::maps::MapRepresentation;
+    use ::ser::SerializerBuilder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inventory {
+        #[serde(rename = "@warehouse")]
+        warehouse: String,
+        stock: BTreeMap<String, Item>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        #[serde(rename = "@unit")]
+        unit: String,
+        count: u32,
+    }
+
+    #[test]
+    fn entries() {
+        setup();
+
+        let mut stock = BTreeMap::new();
+        stock.insert("1st shelf".to_string(), Item { unit: "box".to_string(), count: 3 });
+        stock.insert("a/b".to_string(), Item { unit: "kg".to_string(), count: 12 });
+        let object = Inventory { warehouse: "north".to_string(), stock };
+
+        let xml = SerializerBuilder::new()
+            .map_representation(MapRepresentation::entries())
+            .to_string(&object)
+            .unwrap();
+        debug!("xml: {}", xml);
+
+        let mut de = Deserializer::new_from_reader(xml.as_bytes()).unwrap()
+            .with_map_representation(MapRepresentation::entries());
+        let actual = Inventory::deserialize(&mut de).unwrap();
+
+        assert_eq!(object, actual);
+    }
 }
 
 mod ns {